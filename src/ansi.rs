@@ -0,0 +1,113 @@
+//! Turns text colored with ANSI escape sequences, such as the output of
+//! `bat --color=always`, into styled lines. Only SGR (`ESC [ ... m`) is
+//! interpreted; other escape sequences are dropped.
+
+use ratatui::{
+    style::{Color, Modifier, Style},
+    text::{Line, Span},
+};
+
+pub fn parse(text: &str) -> Vec<Line<'static>> {
+    let mut style = Style::default();
+    text.lines()
+        .map(|line| parse_line(line, &mut style))
+        .collect()
+}
+
+/// Parses one line. `style` carries over from the line before, since
+/// colors aren't necessarily reset at line ends.
+fn parse_line(line: &str, style: &mut Style) -> Line<'static> {
+    let mut spans = Vec::new();
+    let mut text = String::new();
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '\x1b' {
+            text.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('[') => {
+                let mut params = String::new();
+                let mut command = None;
+                for c in chars.by_ref() {
+                    if ('\x40'..='\x7e').contains(&c) {
+                        command = Some(c);
+                        break;
+                    }
+                    params.push(c);
+                }
+                if command == Some('m') {
+                    if !text.is_empty() {
+                        spans.push(Span::styled(std::mem::take(&mut text), *style));
+                    }
+                    *style = apply_sgr(*style, &params);
+                }
+            }
+            // Operating system commands (titles, hyperlinks) end with BEL
+            // or `ESC \`.
+            Some(']') => {
+                while let Some(c) = chars.next() {
+                    if c == '\x07' {
+                        break;
+                    }
+                    if c == '\x1b' && chars.peek() == Some(&'\\') {
+                        chars.next();
+                        break;
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    if !text.is_empty() {
+        spans.push(Span::styled(text, *style));
+    }
+    Line::from(spans)
+}
+
+fn apply_sgr(mut style: Style, params: &str) -> Style {
+    let codes: Vec<u16> = params.split(';').map(|p| p.parse().unwrap_or(0)).collect();
+    let mut codes = codes.iter().copied();
+    while let Some(code) = codes.next() {
+        style = match code {
+            0 => Style::default(),
+            1 => style.add_modifier(Modifier::BOLD),
+            2 => style.add_modifier(Modifier::DIM),
+            3 => style.add_modifier(Modifier::ITALIC),
+            4 => style.add_modifier(Modifier::UNDERLINED),
+            7 => style.add_modifier(Modifier::REVERSED),
+            9 => style.add_modifier(Modifier::CROSSED_OUT),
+            22 => style.remove_modifier(Modifier::BOLD | Modifier::DIM),
+            23 => style.remove_modifier(Modifier::ITALIC),
+            24 => style.remove_modifier(Modifier::UNDERLINED),
+            27 => style.remove_modifier(Modifier::REVERSED),
+            29 => style.remove_modifier(Modifier::CROSSED_OUT),
+            30..=37 => style.fg(Color::Indexed((code - 30) as u8)),
+            38 => match extended_color(&mut codes) {
+                Some(color) => style.fg(color),
+                None => style,
+            },
+            39 => style.fg(Color::Reset),
+            40..=47 => style.bg(Color::Indexed((code - 40) as u8)),
+            48 => match extended_color(&mut codes) {
+                Some(color) => style.bg(color),
+                None => style,
+            },
+            49 => style.bg(Color::Reset),
+            90..=97 => style.fg(Color::Indexed((code - 90 + 8) as u8)),
+            100..=107 => style.bg(Color::Indexed((code - 100 + 8) as u8)),
+            _ => style,
+        };
+    }
+    style
+}
+
+/// Reads the rest of a `38;5;n` or `38;2;r;g;b` color.
+fn extended_color(codes: &mut impl Iterator<Item = u16>) -> Option<Color> {
+    let mut next = || codes.next().map(|c| c.min(255) as u8);
+    match next()? {
+        5 => Some(Color::Indexed(next()?)),
+        2 => Some(Color::Rgb(next()?, next()?, next()?)),
+        _ => None,
+    }
+}
@@ -0,0 +1,105 @@
+//! File checksums, computed on a worker thread so big files don't freeze
+//! the UI while they are read.
+
+use std::{
+    fs::File,
+    io::{self, Read},
+    path::PathBuf,
+    sync::mpsc::{self, Receiver},
+    thread,
+};
+
+use sha2::{Digest, Sha224, Sha256, Sha384, Sha512};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum Algorithm {
+    Sha224,
+    #[default]
+    Sha256,
+    Sha384,
+    Sha512,
+}
+
+impl Algorithm {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().replace('-', "").as_str() {
+            "sha224" => Some(Self::Sha224),
+            "sha256" => Some(Self::Sha256),
+            "sha384" => Some(Self::Sha384),
+            "sha512" => Some(Self::Sha512),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Sha224 => "SHA-224",
+            Self::Sha256 => "SHA-256",
+            Self::Sha384 => "SHA-384",
+            Self::Sha512 => "SHA-512",
+        }
+    }
+}
+
+pub enum Progress {
+    /// Total bytes hashed so far.
+    Hashed(u64),
+    /// The lowercase hex digest, or why the file couldn't be read.
+    Done(io::Result<String>),
+}
+
+/// A checksum being computed in the background.
+pub struct Job {
+    pub path: PathBuf,
+    pub algorithm: Algorithm,
+    pub total: u64,
+    pub rx: Receiver<Progress>,
+}
+
+impl Job {
+    pub fn start(path: PathBuf, algorithm: Algorithm) -> io::Result<Self> {
+        let file = File::open(&path)?;
+        let total = file.metadata()?.len();
+        let (tx, rx) = mpsc::channel();
+        thread::spawn(move || {
+            let report = |hashed| tx.send(Progress::Hashed(hashed)).is_ok();
+            let result = match algorithm {
+                Algorithm::Sha224 => hash::<Sha224>(file, report),
+                Algorithm::Sha256 => hash::<Sha256>(file, report),
+                Algorithm::Sha384 => hash::<Sha384>(file, report),
+                Algorithm::Sha512 => hash::<Sha512>(file, report),
+            };
+            let _ = tx.send(Progress::Done(result));
+        });
+        Ok(Self {
+            path,
+            algorithm,
+            total,
+            rx,
+        })
+    }
+}
+
+/// Hashes `file`, calling `report` after each block. Stops early, with an
+/// `Interrupted` error, once `report` says nobody is listening any more.
+fn hash<D: Digest>(mut file: File, report: impl Fn(u64) -> bool) -> io::Result<String> {
+    let mut hasher = D::new();
+    let mut buf = vec![0; 1 << 20];
+    let mut hashed = 0;
+    loop {
+        let n = file.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+        hashed += n as u64;
+        if !report(hashed) {
+            return Err(io::Error::from(io::ErrorKind::Interrupted));
+        }
+    }
+    Ok(hasher
+        .finalize()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect())
+}
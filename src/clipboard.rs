@@ -0,0 +1,14 @@
+//! Clipboard access through the OSC 52 terminal escape sequence. The
+//! terminal does the copying, so it works over SSH and without a display
+//! server, as long as the terminal emulator supports it.
+
+use std::io::{self, Write};
+
+use base64::Engine;
+
+pub fn copy(text: &str) -> io::Result<()> {
+    let encoded = base64::engine::general_purpose::STANDARD.encode(text);
+    let mut out = io::stdout();
+    write!(out, "\x1b]52;c;{}\x07", encoded)?;
+    out.flush()
+}
@@ -0,0 +1,541 @@
+//! User configuration, read from `$XDG_CONFIG_HOME/lazycat/config.toml`
+//! (falling back to `~/.config`).
+//!
+//! Only the small slice of TOML the options need is understood: `[section]`
+//! headers, `#` comments, and `key = value` pairs whose value is a quoted
+//! string, an integer, a boolean, or an array of those (which may span
+//! several lines).
+
+use std::{collections::HashMap, env, fs, io, path::PathBuf};
+
+use ratatui::style::Color;
+
+use crate::{
+    checksum,
+    keymap::{self, Action, Keymap},
+    statusline::StatusFormat,
+};
+
+#[derive(Debug, Clone, PartialEq)]
+enum Value {
+    String(String),
+    Integer(i64),
+    Boolean(bool),
+    Array(Vec<Value>),
+}
+
+/// Where the "emit selection" action delivers the selected path.
+#[derive(Debug, Clone)]
+pub enum EmitTarget {
+    /// A command line run through the shell, with `%` replaced by the path
+    /// and `%%` by a literal `%`.
+    Command(String),
+    /// A named pipe or Unix socket that receives the path and a newline.
+    Socket(PathBuf),
+}
+
+/// How file extensions are drawn in the listing. Only the display changes;
+/// operations always see the full name.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum ExtensionDisplay {
+    #[default]
+    Full,
+    Hidden,
+    /// Extensions pulled out into a right-aligned column.
+    Column,
+}
+
+impl ExtensionDisplay {
+    pub fn next(self) -> Self {
+        match self {
+            Self::Full => Self::Hidden,
+            Self::Hidden => Self::Column,
+            Self::Column => Self::Full,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Full => "full",
+            Self::Hidden => "hidden",
+            Self::Column => "column",
+        }
+    }
+}
+
+/// What the preview's line numbers (`zn`) count.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum LineNumbers {
+    #[default]
+    Absolute,
+    /// How far each line is from the current one, vim `relativenumber`
+    /// style.
+    Relative,
+    /// Relative, except that the current line shows its own number.
+    Hybrid,
+}
+
+/// The line relative numbers count from.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum CurrentLine {
+    /// The top line in view.
+    #[default]
+    Top,
+    /// The line halfway down the pane.
+    Middle,
+}
+
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub emit: Option<EmitTarget>,
+    pub extension_display: ExtensionDisplay,
+    pub checksum_algorithm: checksum::Algorithm,
+    pub swap_panes: bool,
+    pub keep_stale_preview: bool,
+    /// The syntect theme to start with, if not the built-in default.
+    pub theme: Option<String>,
+    /// How much of a file too small to page the preview reads.
+    pub preview_byte_limit: usize,
+    /// Let `bat` highlight previews when it is installed.
+    pub use_bat: bool,
+    pub line_numbers: LineNumbers,
+    pub current_line: CurrentLine,
+    /// Whether the preview starts with the column guide drawn.
+    pub guide: bool,
+    /// The 1-based column the guide is drawn at.
+    pub guide_column: u16,
+    pub guide_color: Color,
+    pub two_line_entries: bool,
+    /// Put a Nerd Font glyph for its type before each entry.
+    pub use_icons: bool,
+    /// Whether dotfiles are listed from the start.
+    pub show_hidden: bool,
+    /// Badge directories with how many entries they hold.
+    pub dir_counts: bool,
+    /// Badge files that have more than one hard link.
+    pub show_hard_links: bool,
+    /// Tint names by how recently they were modified.
+    pub heatmap: bool,
+    /// Scroll the listing so the selection stays in the middle row.
+    pub center_selection: bool,
+    /// The tint for something modified just now.
+    pub heatmap_recent: (u8, u8, u8),
+    /// The tint for something at least `heatmap_days` old.
+    pub heatmap_old: (u8, u8, u8),
+    pub heatmap_days: u64,
+    /// Read at most this many entries of a directory, until asked for the
+    /// rest. `None` reads everything.
+    pub max_entries: Option<usize>,
+    /// Globs of generated files whose preview is suppressed by default.
+    pub generated_files: Vec<String>,
+    pub status_format: StatusFormat,
+    pub templates_dir: Option<PathBuf>,
+    /// Overrides how `!` opens a shell; `%` is the directory.
+    pub split_command: Option<String>,
+    pub keys: Keymap,
+}
+
+/// Generated artifacts that are rarely worth highlighting.
+const DEFAULT_GENERATED_FILES: &[&str] = &[
+    "*.min.js",
+    "*.min.css",
+    "*.map",
+    "package-lock.json",
+    "yarn.lock",
+    "pnpm-lock.yaml",
+];
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            emit: None,
+            extension_display: ExtensionDisplay::default(),
+            checksum_algorithm: checksum::Algorithm::default(),
+            swap_panes: false,
+            keep_stale_preview: false,
+            theme: None,
+            preview_byte_limit: 50_000,
+            use_bat: false,
+            line_numbers: LineNumbers::default(),
+            current_line: CurrentLine::default(),
+            guide: false,
+            guide_column: 80,
+            guide_color: Color::Indexed(236),
+            two_line_entries: false,
+            use_icons: false,
+            show_hidden: false,
+            dir_counts: false,
+            show_hard_links: false,
+            heatmap: false,
+            center_selection: false,
+            heatmap_recent: (0xff, 0xd7, 0x5f),
+            heatmap_old: (0x58, 0x58, 0x58),
+            heatmap_days: 30,
+            max_entries: Some(10_000),
+            generated_files: DEFAULT_GENERATED_FILES
+                .iter()
+                .map(|s| s.to_string())
+                .collect(),
+            status_format: StatusFormat::default(),
+            templates_dir: config_dir().map(|d| d.join("templates")),
+            split_command: None,
+            keys: keymap::default_keymap(),
+        }
+    }
+}
+
+/// `$XDG_CONFIG_HOME/lazycat`, or `~/.config/lazycat`.
+fn config_dir() -> Option<PathBuf> {
+    let dir = env::var_os("XDG_CONFIG_HOME")
+        .filter(|v| !v.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+    Some(dir.join("lazycat"))
+}
+
+fn config_file() -> Option<PathBuf> {
+    Some(config_dir()?.join("config.toml"))
+}
+
+fn parse_value(raw: &str) -> Option<Value> {
+    if let Some(inner) = raw.strip_prefix('"') {
+        let inner = inner.strip_suffix('"')?;
+        let mut out = String::new();
+        let mut chars = inner.chars();
+        while let Some(c) = chars.next() {
+            if c != '\\' {
+                out.push(c);
+                continue;
+            }
+            match chars.next()? {
+                'n' => out.push('\n'),
+                't' => out.push('\t'),
+                c @ ('"' | '\\') => out.push(c),
+                _ => return None,
+            }
+        }
+        return Some(Value::String(out));
+    }
+    if let Some(inner) = raw.strip_prefix('[') {
+        let inner = inner.strip_suffix(']')?;
+        return split_items(inner)
+            .into_iter()
+            .map(parse_value)
+            .collect::<Option<_>>()
+            .map(Value::Array);
+    }
+    if let Some(inner) = raw.strip_prefix('\'') {
+        return Some(Value::String(inner.strip_suffix('\'')?.to_string()));
+    }
+    match raw {
+        "true" => Some(Value::Boolean(true)),
+        "false" => Some(Value::Boolean(false)),
+        _ => raw.replace('_', "").parse().ok().map(Value::Integer),
+    }
+}
+
+/// Splits the inside of an array on the commas that aren't quoted, dropping
+/// the empty item a trailing comma leaves.
+fn split_items(inner: &str) -> Vec<&str> {
+    let mut items = Vec::new();
+    let mut quote = None;
+    let mut start = 0;
+    for (i, c) in inner.char_indices() {
+        match (quote, c) {
+            (None, '"' | '\'') => quote = Some(c),
+            (Some(q), _) if q == c => quote = None,
+            (None, ',') => {
+                items.push(inner[start..i].trim());
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    items.push(inner[start..].trim());
+    items.retain(|item| !item.is_empty());
+    items
+}
+
+/// Strips a trailing `# comment`, leaving `#` inside quoted strings alone.
+fn strip_comment(line: &str) -> &str {
+    let mut quote = None;
+    for (i, c) in line.char_indices() {
+        match (quote, c) {
+            (None, '"' | '\'') => quote = Some(c),
+            (Some(q), _) if q == c => quote = None,
+            (None, '#') => return &line[..i],
+            _ => {}
+        }
+    }
+    line
+}
+
+/// Flattens the file into `section.key` entries.
+fn parse(content: &str) -> Result<HashMap<String, Value>, String> {
+    let mut table = HashMap::new();
+    let mut section = String::new();
+    let mut lines = content.lines().enumerate();
+    while let Some((number, line)) = lines.next() {
+        let line = strip_comment(line).trim();
+        if line.is_empty() {
+            continue;
+        }
+        if let Some(name) = line.strip_prefix('[') {
+            let name = name
+                .strip_suffix(']')
+                .ok_or_else(|| format!("line {}: unterminated section header", number + 1))?;
+            section = format!("{}.", name.trim());
+            continue;
+        }
+        let (key, raw) = line
+            .split_once('=')
+            .ok_or_else(|| format!("line {}: expected `key = value`", number + 1))?;
+        let mut raw = raw.trim().to_string();
+        if raw.starts_with('[') {
+            // Arrays may continue over the following lines until the `]`.
+            while !raw.ends_with(']') {
+                let (_, next) = lines
+                    .next()
+                    .ok_or_else(|| format!("line {}: unterminated array", number + 1))?;
+                raw.push(' ');
+                raw.push_str(strip_comment(next).trim());
+            }
+        }
+        let value = parse_value(&raw)
+            .ok_or_else(|| format!("line {}: unsupported value `{}`", number + 1, raw))?;
+        table.insert(format!("{}{}", section, key.trim()), value);
+    }
+    Ok(table)
+}
+
+/// Expands a leading `~/` to the home directory.
+fn expand_home(path: &str) -> PathBuf {
+    match (path.strip_prefix("~/"), env::var_os("HOME")) {
+        (Some(rest), Some(home)) => PathBuf::from(home).join(rest),
+        _ => PathBuf::from(path),
+    }
+}
+
+fn get_string(table: &HashMap<String, Value>, key: &str) -> Result<Option<String>, String> {
+    match table.get(key) {
+        None => Ok(None),
+        Some(Value::String(s)) => Ok(Some(s.clone())),
+        Some(_) => Err(format!("`{}` must be a string", key)),
+    }
+}
+
+/// Reads an array of strings.
+fn get_strings(table: &HashMap<String, Value>, key: &str) -> Result<Option<Vec<String>>, String> {
+    let error = || format!("`{}` must be an array of strings", key);
+    match table.get(key) {
+        None => Ok(None),
+        Some(Value::Array(items)) => items
+            .iter()
+            .map(|item| match item {
+                Value::String(s) => Ok(s.clone()),
+                _ => Err(error()),
+            })
+            .collect::<Result<_, _>>()
+            .map(Some),
+        Some(_) => Err(error()),
+    }
+}
+
+fn get_bool(table: &HashMap<String, Value>, key: &str) -> Result<Option<bool>, String> {
+    match table.get(key) {
+        None => Ok(None),
+        Some(Value::Boolean(b)) => Ok(Some(*b)),
+        Some(_) => Err(format!("`{}` must be true or false", key)),
+    }
+}
+
+fn get_integer(table: &HashMap<String, Value>, key: &str) -> Result<Option<i64>, String> {
+    match table.get(key) {
+        None => Ok(None),
+        Some(Value::Integer(n)) => Ok(Some(*n)),
+        Some(_) => Err(format!("`{}` must be an integer", key)),
+    }
+}
+
+/// Applies the `[keys]` section, `action = "key"` lines, to the default
+/// bindings.
+fn get_keys(table: &HashMap<String, Value>) -> Result<Keymap, String> {
+    let mut keys = keymap::default_keymap();
+    let mut bindings: Vec<_> = table
+        .iter()
+        .filter_map(|(key, value)| Some((key.strip_prefix("keys.")?, value)))
+        .collect();
+    // Applied in a fixed order, so that binding two actions to one key
+    // always ends the same way.
+    bindings.sort_by_key(|(name, _)| *name);
+    for (name, value) in bindings {
+        let action =
+            Action::from_name(name).ok_or_else(|| format!("unknown action `keys.{}`", name))?;
+        let mut chars = match value {
+            Value::String(s) => s.chars(),
+            _ => "".chars(),
+        };
+        let (Some(key), None) = (chars.next(), chars.next()) else {
+            return Err(format!("`keys.{}` must be a single character", name));
+        };
+        if keymap::PREFIX_KEYS.contains(&key) {
+            return Err(format!(
+                "`keys.{}` can't be `{}`, which starts two-key commands",
+                name, key
+            ));
+        }
+        keymap::rebind(&mut keys, action, key);
+    }
+    Ok(keys)
+}
+
+/// Reads a `"#rrggbb"` color. Gradients need the components, so named and
+/// indexed colors aren't accepted here.
+fn get_rgb(table: &HashMap<String, Value>, key: &str) -> Result<Option<(u8, u8, u8)>, String> {
+    match get_string(table, key)? {
+        None => Ok(None),
+        Some(hex) => match hex.parse() {
+            Ok(Color::Rgb(r, g, b)) => Ok(Some((r, g, b))),
+            _ => Err(format!("`{}` must be a \"#rrggbb\" color", key)),
+        },
+    }
+}
+
+impl Config {
+    /// Loads the config file. A missing file yields the defaults; a file that
+    /// can't be read or parsed also yields the defaults, along with a warning
+    /// describing what went wrong.
+    pub fn load() -> (Self, Option<String>) {
+        let Some(path) = config_file() else {
+            return (Self::default(), None);
+        };
+        let content = match fs::read_to_string(&path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return (Self::default(), None),
+            Err(e) => return (Self::default(), Some(format!("{}: {}", path.display(), e))),
+        };
+        match parse(&content).and_then(|table| Self::from_table(&table)) {
+            Ok(config) => (config, None),
+            Err(e) => (
+                Self::default(),
+                Some(format!("{}: {}, using defaults", path.display(), e)),
+            ),
+        }
+    }
+
+    fn from_table(table: &HashMap<String, Value>) -> Result<Self, String> {
+        let command = get_string(table, "emit.command")?;
+        let socket = get_string(table, "emit.socket")?;
+        let emit = match (command, socket) {
+            (Some(_), Some(_)) => {
+                return Err("set only one of `emit.command` and `emit.socket`".to_string());
+            }
+            (Some(command), None) => Some(EmitTarget::Command(command)),
+            (None, Some(socket)) => Some(EmitTarget::Socket(PathBuf::from(socket))),
+            (None, None) => None,
+        };
+        let extension_display = match get_string(table, "list.extensions")?.as_deref() {
+            None => ExtensionDisplay::default(),
+            Some("full") => ExtensionDisplay::Full,
+            Some("hidden") => ExtensionDisplay::Hidden,
+            Some("column") => ExtensionDisplay::Column,
+            Some(_) => {
+                return Err(
+                    "`list.extensions` must be \"full\", \"hidden\" or \"column\"".to_string(),
+                );
+            }
+        };
+        let line_numbers = match get_string(table, "preview.line_numbers")?.as_deref() {
+            None => LineNumbers::default(),
+            Some("absolute") => LineNumbers::Absolute,
+            Some("relative") => LineNumbers::Relative,
+            Some("hybrid") => LineNumbers::Hybrid,
+            Some(_) => {
+                return Err(
+                    "`preview.line_numbers` must be \"absolute\", \"relative\" or \"hybrid\""
+                        .to_string(),
+                );
+            }
+        };
+        let current_line = match get_string(table, "preview.current_line")?.as_deref() {
+            None => CurrentLine::default(),
+            Some("top") => CurrentLine::Top,
+            Some("middle") => CurrentLine::Middle,
+            Some(_) => {
+                return Err("`preview.current_line` must be \"top\" or \"middle\"".to_string());
+            }
+        };
+        let checksum_algorithm = match get_string(table, "checksum.algorithm")? {
+            None => checksum::Algorithm::default(),
+            Some(name) => checksum::Algorithm::from_name(&name).ok_or_else(|| {
+                format!("unknown `checksum.algorithm` `{}` (try \"sha256\")", name)
+            })?,
+        };
+        Ok(Self {
+            emit,
+            extension_display,
+            checksum_algorithm,
+            swap_panes: get_bool(table, "layout.swap_panes")?.unwrap_or(false),
+            keep_stale_preview: get_bool(table, "preview.keep_stale")?.unwrap_or(false),
+            theme: get_string(table, "preview.theme")?,
+            preview_byte_limit: match get_integer(table, "preview.max_bytes")? {
+                None => Self::default().preview_byte_limit,
+                Some(n) => usize::try_from(n)
+                    .ok()
+                    .filter(|n| *n > 0)
+                    .ok_or_else(|| "`preview.max_bytes` must be a positive number".to_string())?,
+            },
+            use_bat: get_bool(table, "preview.bat")?.unwrap_or(false),
+            line_numbers,
+            current_line,
+            guide: get_bool(table, "preview.guide")?.unwrap_or(false),
+            guide_column: match get_integer(table, "preview.guide_column")? {
+                None => Self::default().guide_column,
+                Some(n) => u16::try_from(n).ok().filter(|n| *n > 0).ok_or_else(|| {
+                    "`preview.guide_column` must be a positive column".to_string()
+                })?,
+            },
+            guide_color: match get_string(table, "preview.guide_color")? {
+                None => Self::default().guide_color,
+                Some(name) => name
+                    .parse()
+                    .map_err(|_| format!("unknown `preview.guide_color` `{}`", name))?,
+            },
+            two_line_entries: get_bool(table, "list.two_line")?.unwrap_or(false),
+            use_icons: get_bool(table, "list.icons")?.unwrap_or(false),
+            show_hidden: get_bool(table, "list.hidden")?.unwrap_or(false),
+            dir_counts: get_bool(table, "list.dir_counts")?.unwrap_or(false),
+            show_hard_links: get_bool(table, "list.hard_links")?.unwrap_or(false),
+            heatmap: get_bool(table, "list.heatmap")?.unwrap_or(false),
+            center_selection: get_bool(table, "list.centered")?.unwrap_or(false),
+            heatmap_recent: get_rgb(table, "heatmap.recent")?
+                .unwrap_or_else(|| Self::default().heatmap_recent),
+            heatmap_old: get_rgb(table, "heatmap.old")?
+                .unwrap_or_else(|| Self::default().heatmap_old),
+            heatmap_days: match get_integer(table, "heatmap.days")? {
+                None => Self::default().heatmap_days,
+                Some(n) => u64::try_from(n)
+                    .ok()
+                    .filter(|n| *n > 0)
+                    .ok_or_else(|| "`heatmap.days` must be a positive number".to_string())?,
+            },
+            max_entries: match get_integer(table, "list.max_entries")? {
+                None => Self::default().max_entries,
+                Some(0) => None,
+                Some(n) => Some(
+                    usize::try_from(n)
+                        .map_err(|_| "`list.max_entries` can't be negative".to_string())?,
+                ),
+            },
+            generated_files: get_strings(table, "preview.generated")?
+                .unwrap_or_else(|| Self::default().generated_files),
+            status_format: get_string(table, "status.format")?
+                .map_or_else(StatusFormat::default, |f| StatusFormat::parse(&f)),
+            templates_dir: get_string(table, "templates.dir")?
+                .map(|d| expand_home(&d))
+                .or_else(|| Self::default().templates_dir),
+            split_command: get_string(table, "split.command")?,
+            keys: get_keys(table)?,
+        })
+    }
+}
@@ -0,0 +1,82 @@
+//! Unified diff coloring for `.diff` and `.patch` files, and for anything
+//! else that opens like `git diff` output.
+
+use std::path::Path;
+
+use ratatui::{
+    style::{Color, Modifier, Style},
+    text::{Line, Span},
+};
+
+/// Whether `path` should be read as a diff: by extension, or because its
+/// first non-blank line opens one.
+pub fn is_diff(path: &Path, content: &str) -> bool {
+    let by_extension = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| matches!(e.to_ascii_lowercase().as_str(), "diff" | "patch"));
+    by_extension
+        || content
+            .lines()
+            .find(|l| !l.trim().is_empty())
+            .is_some_and(|first| {
+                ["diff ", "--- ", "+++ ", "@@ "]
+                    .iter()
+                    .any(|prefix| first.starts_with(prefix))
+            })
+}
+
+/// Line counts from a hunk header such as `@@ -12,7 +12,9 @@`: how many
+/// old and new lines the hunk body holds.
+fn hunk_lengths(header: &str) -> Option<(u64, u64)> {
+    let mut ranges = header.strip_prefix("@@ ")?.split_whitespace();
+    let length = |range: Option<&str>, sign: char| -> Option<u64> {
+        let range = range?.strip_prefix(sign)?;
+        match range.split_once(',') {
+            Some((_, length)) => length.parse().ok(),
+            None => Some(1),
+        }
+    };
+    Some((length(ranges.next(), '-')?, length(ranges.next(), '+')?))
+}
+
+/// Colors `content` line by line, or returns `None` if it holds no hunk at
+/// all, in which case it is better off with ordinary highlighting.
+pub fn render(content: &str) -> Option<Vec<Line<'static>>> {
+    let header = Style::default().add_modifier(Modifier::BOLD);
+    let mut lines = Vec::new();
+    // Old and new lines still to come in the current hunk.
+    let (mut old, mut new): (u64, u64) = (0, 0);
+    let mut saw_hunk = false;
+    for line in content.lines() {
+        let in_hunk = old > 0 || new > 0;
+        let style = if in_hunk && line.starts_with('+') {
+            new = new.saturating_sub(1);
+            Style::default().fg(Color::Green)
+        } else if in_hunk && line.starts_with('-') {
+            old = old.saturating_sub(1);
+            Style::default().fg(Color::Red)
+        } else if in_hunk && (line.starts_with(' ') || line.is_empty()) {
+            old = old.saturating_sub(1);
+            new = new.saturating_sub(1);
+            Style::default()
+        } else if line.starts_with('\\') {
+            // `\ No newline at end of file`
+            Style::default().fg(Color::DarkGray)
+        } else if let Some((old_len, new_len)) = hunk_lengths(line) {
+            (old, new) = (old_len, new_len);
+            saw_hunk = true;
+            Style::default().fg(Color::Cyan)
+        } else if ["diff ", "--- ", "+++ ", "index "]
+            .iter()
+            .any(|prefix| line.starts_with(prefix))
+        {
+            header
+        } else {
+            // Commit messages and other prose around the hunks.
+            Style::default().fg(Color::DarkGray)
+        };
+        lines.push(Line::from(Span::styled(line.to_string(), style)));
+    }
+    saw_hunk.then_some(lines)
+}
@@ -0,0 +1,121 @@
+//! Hands the selected path to another program, e.g. an editor server that
+//! is already running, as configured under `[emit]`.
+
+use std::{
+    path::Path,
+    process::{Command, Stdio},
+};
+
+use crate::config::EmitTarget;
+
+/// Quotes `s` for a POSIX shell so it survives as a single word.
+pub fn shell_quote(s: &str) -> String {
+    format!("'{}'", s.replace('\'', r"'\''"))
+}
+
+/// Fills in `template`: `%` becomes the shell-quoted path and `%%` a
+/// literal `%`, for commands like `date +%%s`.
+pub fn substitute(template: &str, path: &Path) -> String {
+    let quoted = shell_quote(&path.to_string_lossy());
+    let mut command = String::new();
+    let mut chars = template.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '%' if chars.next_if_eq(&'%').is_some() => command.push('%'),
+            '%' => command.push_str(&quoted),
+            c => command.push(c),
+        }
+    }
+    command
+}
+
+/// Delivers `path` to `target`, returning a short description of where it
+/// went on success. Waits for a command to finish, so run it off the UI
+/// thread.
+pub fn emit(target: &EmitTarget, path: &Path) -> Result<String, String> {
+    match target {
+        EmitTarget::Command(template) => {
+            let command = substitute(template, path);
+            let output = Command::new("sh")
+                .arg("-c")
+                .arg(&command)
+                .stdin(Stdio::null())
+                .output()
+                .map_err(|e| format!("cannot run `{}`: {}", command, e))?;
+            if output.status.success() {
+                Ok(format!("`{}`", command))
+            } else {
+                let stderr = String::from_utf8_lossy(&output.stderr);
+                let reason = stderr.lines().next().unwrap_or("").trim().to_string();
+                Err(format!(
+                    "`{}` failed ({}) {}",
+                    command, output.status, reason
+                ))
+            }
+        }
+        EmitTarget::Socket(socket) => write_socket(socket, path)
+            .map(|()| socket.display().to_string())
+            .map_err(|e| format!("{}: {}", socket.display(), e)),
+    }
+}
+
+#[cfg(unix)]
+fn write_socket(socket: &Path, path: &Path) -> std::io::Result<()> {
+    use std::{
+        fs::OpenOptions,
+        io::Write,
+        os::unix::{
+            ffi::OsStrExt,
+            fs::{FileTypeExt, OpenOptionsExt},
+            net::UnixStream,
+        },
+    };
+
+    let mut message = path.as_os_str().as_bytes().to_vec();
+    message.push(b'\n');
+    if std::fs::metadata(socket)?.file_type().is_fifo() {
+        // Without O_NONBLOCK, opening a pipe nobody reads would hang the UI;
+        // with it the open fails straight away instead.
+        let mut pipe = OpenOptions::new()
+            .write(true)
+            .custom_flags(libc::O_NONBLOCK)
+            .open(socket)?;
+        pipe.write_all(&message)
+    } else {
+        UnixStream::connect(socket)?.write_all(&message)
+    }
+}
+
+#[cfg(not(unix))]
+fn write_socket(_socket: &Path, _path: &Path) -> std::io::Result<()> {
+    Err(std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        "sockets and named pipes are only supported on Unix",
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn substitutes_the_quoted_path() {
+        let path = Path::new("/tmp/it's here");
+        assert_eq!(
+            substitute("nvim --remote %", path),
+            r"nvim --remote '/tmp/it'\''s here'"
+        );
+        assert_eq!(substitute("echo % %", Path::new("/a")), "echo '/a' '/a'");
+    }
+
+    #[test]
+    fn double_percent_is_literal() {
+        let path = Path::new("/a");
+        assert_eq!(
+            substitute("date +%%s; printf %%s %", path),
+            "date +%s; printf %s '/a'"
+        );
+        assert_eq!(substitute("100%%%", path), "100%'/a'");
+        assert_eq!(substitute("no placeholder", path), "no placeholder");
+    }
+}
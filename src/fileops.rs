@@ -0,0 +1,283 @@
+//! Moving and copying files and directory trees.
+
+use std::{
+    ffi::OsString,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+/// A path in `dir` for something called `name` that doesn't exist yet:
+/// `name` itself if free, otherwise `stem-1.ext`, `stem-2.ext`, and so on.
+pub fn unique_destination(dir: &Path, name: &Path) -> PathBuf {
+    free_name(dir, name, false, |n| format!("-{}", n))
+}
+
+/// A path in `dir` for a copy of `source`, numbered the way file managers
+/// name copies: its own name if free, otherwise `stem (1).ext`,
+/// `stem (2).ext`, and so on. A directory keeps its whole name in front,
+/// as in `v1.2 (1)`.
+pub fn numbered_destination(dir: &Path, source: &Path) -> PathBuf {
+    let name = Path::new(source.file_name().unwrap_or(source.as_os_str()));
+    let is_dir = fs::symlink_metadata(source).is_ok_and(|m| m.is_dir());
+    free_name(dir, name, is_dir, |n| format!(" ({})", n))
+}
+
+/// `name` in `dir` if free, otherwise the first free one with `suffix(n)`
+/// put before its extension, or after all of it if `whole`.
+fn free_name(dir: &Path, name: &Path, whole: bool, suffix: impl Fn(usize) -> String) -> PathBuf {
+    let candidate = dir.join(name);
+    if fs::symlink_metadata(&candidate).is_err() {
+        return candidate;
+    }
+    let (stem, extension) = if whole {
+        (name.as_os_str(), None)
+    } else {
+        let stem = name.file_stem().unwrap_or(name.as_os_str());
+        (stem, name.extension())
+    };
+    (1..)
+        .map(|n| {
+            let mut file_name = OsString::from(stem);
+            file_name.push(suffix(n));
+            if let Some(ext) = extension {
+                file_name.push(".");
+                file_name.push(ext);
+            }
+            dir.join(file_name)
+        })
+        .find(|path| fs::symlink_metadata(path).is_err())
+        .expect("some suffix is free")
+}
+
+/// Copies a file, or a directory and everything under it, to `to`, which
+/// must not exist. Symlinks are copied as links on Unix rather than
+/// followed. A copy that fails partway is removed again.
+pub fn copy_recursive(from: &Path, to: &Path) -> io::Result<()> {
+    if fs::symlink_metadata(to).is_ok() {
+        return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!("{} already exists", to.display()),
+        ));
+    }
+    copy_tree(from, to).inspect_err(|_| {
+        // Best effort: the copy's own error says more than this one would.
+        let _ = remove_entry(to);
+    })
+}
+
+fn copy_tree(from: &Path, to: &Path) -> io::Result<()> {
+    let metadata = fs::symlink_metadata(from)?;
+    if metadata.is_dir() {
+        fs::create_dir(to)?;
+        for entry in fs::read_dir(from)? {
+            let entry = entry?;
+            copy_tree(&entry.path(), &to.join(entry.file_name()))?;
+        }
+        fs::set_permissions(to, metadata.permissions())
+    } else if metadata.file_type().is_symlink() {
+        copy_symlink(from, to)
+    } else {
+        fs::copy(from, to).map(|_| ())
+    }
+}
+
+#[cfg(unix)]
+fn copy_symlink(from: &Path, to: &Path) -> io::Result<()> {
+    std::os::unix::fs::symlink(fs::read_link(from)?, to)
+}
+
+#[cfg(not(unix))]
+fn copy_symlink(from: &Path, to: &Path) -> io::Result<()> {
+    fs::copy(from, to).map(|_| ())
+}
+
+/// Renames `from` to `to`, copying and then deleting the original when they
+/// are on different filesystems.
+pub fn move_path(from: &Path, to: &Path) -> io::Result<()> {
+    match fs::rename(from, to) {
+        Err(e) if e.kind() == io::ErrorKind::CrossesDevices => {
+            copy_recursive(from, to)?;
+            remove_entry(from)
+        }
+        result => result,
+    }
+}
+
+/// Moves `from` to `to` if `cut`, otherwise copies it there.
+pub fn transfer(from: &Path, to: &Path, cut: bool) -> io::Result<()> {
+    if cut {
+        move_path(from, to)
+    } else {
+        copy_recursive(from, to)
+    }
+}
+
+/// Transfers `from` over the existing `to`. The old entry is renamed aside
+/// first, then deleted once `from` is in place, or put back if that fails,
+/// so a failed paste leaves it as it was.
+pub fn replace(from: &Path, to: &Path, cut: bool) -> io::Result<()> {
+    let (Some(dir), Some(name)) = (to.parent(), to.file_name()) else {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("cannot replace {}", to.display()),
+        ));
+    };
+    let mut aside_name = OsString::from(".");
+    aside_name.push(name);
+    aside_name.push(".replaced");
+    let aside = unique_destination(dir, Path::new(&aside_name));
+    fs::rename(to, &aside)?;
+    match transfer(from, to, cut) {
+        Ok(()) => remove_entry(&aside).map_err(|e| {
+            let kept = format!("cannot remove the old entry, kept as {}", aside.display());
+            io::Error::new(e.kind(), format!("{}: {}", kept, e))
+        }),
+        // A move that copied everything but couldn't delete the original
+        // leaves `to` in place; the old entry must not clobber that.
+        Err(e) if fs::symlink_metadata(to).is_ok() => Err(io::Error::new(
+            e.kind(),
+            format!("{}; the old entry is kept as {}", e, aside.display()),
+        )),
+        Err(e) => {
+            fs::rename(&aside, to).map_err(|restore| {
+                io::Error::new(
+                    e.kind(),
+                    format!("{}; cannot put back {}: {}", e, aside.display(), restore),
+                )
+            })?;
+            Err(e)
+        }
+    }
+}
+
+/// Deletes a file, or a directory with everything in it. A link is removed
+/// itself, never what it points to.
+pub fn remove_entry(path: &Path) -> io::Result<()> {
+    if fs::symlink_metadata(path)?.is_dir() {
+        fs::remove_dir_all(path)
+    } else {
+        fs::remove_file(path)
+    }
+}
+
+/// Whether `a` and `b` are one directory entry spelled in a different
+/// case, as a case-only rename finds on case-insensitive filesystems.
+/// Links are not followed, and hard links to one file don't count.
+pub fn same_entry(a: &Path, b: &Path) -> bool {
+    let name = |p: &Path| p.file_name().map(|n| n.to_string_lossy().to_lowercase());
+    a.parent() == b.parent() && name(a).is_some() && name(a) == name(b) && same_file(a, b)
+}
+
+#[cfg(unix)]
+fn same_file(a: &Path, b: &Path) -> bool {
+    use std::os::unix::fs::MetadataExt;
+
+    match (fs::symlink_metadata(a), fs::symlink_metadata(b)) {
+        (Ok(a), Ok(b)) => a.dev() == b.dev() && a.ino() == b.ino(),
+        _ => false,
+    }
+}
+
+/// Canonical paths come back in the case stored on disk.
+#[cfg(not(unix))]
+fn same_file(a: &Path, b: &Path) -> bool {
+    match (fs::canonicalize(a), fs::canonicalize(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An empty directory of its own for each test.
+    fn scratch(test: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("lazycat-{}-{}", test, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir(&dir).unwrap();
+        dir
+    }
+
+    /// The names in `dir`, sorted.
+    fn names(dir: &Path) -> Vec<String> {
+        let mut names: Vec<String> = fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        names.sort();
+        names
+    }
+
+    /// A directory holding a file and something `fs::copy` can't read.
+    #[cfg(unix)]
+    fn uncopyable(dir: &Path) -> PathBuf {
+        let source = dir.join("source");
+        fs::create_dir(&source).unwrap();
+        fs::write(source.join("a"), "a").unwrap();
+        std::os::unix::net::UnixListener::bind(source.join("socket")).unwrap();
+        source
+    }
+
+    #[test]
+    fn numbered_destination_counts_up_from_1() {
+        let dir = scratch("numbered");
+        let file = dir.join("notes.txt");
+        assert_eq!(numbered_destination(&dir, &file), file);
+        fs::write(&file, "").unwrap();
+        assert_eq!(numbered_destination(&dir, &file), dir.join("notes (1).txt"));
+        fs::write(dir.join("notes (1).txt"), "").unwrap();
+        assert_eq!(numbered_destination(&dir, &file), dir.join("notes (2).txt"));
+        let versioned = dir.join("v1.2");
+        fs::create_dir(&versioned).unwrap();
+        assert_eq!(numbered_destination(&dir, &versioned), dir.join("v1.2 (1)"));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn copy_recursive_leaves_an_existing_target_alone() {
+        let dir = scratch("copy-existing");
+        fs::write(dir.join("from"), "new").unwrap();
+        fs::write(dir.join("to"), "old").unwrap();
+        let copied = copy_recursive(&dir.join("from"), &dir.join("to"));
+        assert_eq!(copied.unwrap_err().kind(), io::ErrorKind::AlreadyExists);
+        assert_eq!(fs::read_to_string(dir.join("to")).unwrap(), "old");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn copy_recursive_removes_a_partial_copy() {
+        let dir = scratch("copy-partial");
+        let source = uncopyable(&dir);
+        assert!(copy_recursive(&source, &dir.join("copy")).is_err());
+        assert_eq!(names(&dir), ["source"]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn replace_swaps_a_directory_for_a_file() {
+        let dir = scratch("replace");
+        fs::write(dir.join("from"), "new").unwrap();
+        fs::create_dir(dir.join("to")).unwrap();
+        fs::write(dir.join("to").join("old"), "old").unwrap();
+        replace(&dir.join("from"), &dir.join("to"), true).unwrap();
+        assert_eq!(names(&dir), ["to"]);
+        assert_eq!(fs::read_to_string(dir.join("to")).unwrap(), "new");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn replace_puts_the_old_entry_back_when_pasting_fails() {
+        let dir = scratch("replace-failed");
+        let source = uncopyable(&dir);
+        let target = dir.join("target");
+        fs::create_dir(&target).unwrap();
+        fs::write(target.join("kept"), "old").unwrap();
+        assert!(replace(&source, &target, false).is_err());
+        assert_eq!(names(&dir), ["source", "target"]);
+        assert_eq!(names(&target), ["kept"]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,121 @@
+//! Human-readable renderings of file metadata.
+
+use std::{fs::Metadata, time::SystemTime};
+
+/// Formats a byte count with binary units, e.g. `1.5 MiB`.
+pub fn human_size(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit + 1 < UNITS.len() {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} B", bytes)
+    } else {
+        format!("{:.1} {}", size, UNITS[unit])
+    }
+}
+
+/// Splits `time` into local (days since the epoch, seconds into that day).
+fn local_days_and_seconds(time: SystemTime) -> (i64, i64) {
+    let secs = match time.duration_since(SystemTime::UNIX_EPOCH) {
+        Ok(d) => d.as_secs() as i64,
+        Err(e) => -(e.duration().as_secs() as i64),
+    };
+    let secs = secs + utc_offset(secs);
+    (secs.div_euclid(86_400), secs.rem_euclid(86_400))
+}
+
+/// Formats `time` as `YYYY-MM-DD HH:MM` in the local time zone (UTC where
+/// the zone can't be looked up).
+pub fn local_time(time: SystemTime) -> String {
+    let (days, rem) = local_days_and_seconds(time);
+    format!("{} {:02}:{:02}", date(days), rem / 3600, rem % 3600 / 60)
+}
+
+/// Formats `time` as a local `YYYY-MM-DD` date.
+pub fn local_date(time: SystemTime) -> String {
+    date(local_days_and_seconds(time).0)
+}
+
+fn date(days: i64) -> String {
+    let (year, month, day) = civil_from_days(days);
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+#[cfg(unix)]
+fn utc_offset(secs: i64) -> i64 {
+    let time = secs as libc::time_t;
+    // SAFETY: `tm` is plain old data that `localtime_r` fills in; both
+    // pointers are valid for the duration of the call.
+    unsafe {
+        let mut tm: libc::tm = std::mem::zeroed();
+        if libc::localtime_r(&time, &mut tm).is_null() {
+            0
+        } else {
+            tm.tm_gmtoff as i64
+        }
+    }
+}
+
+#[cfg(not(unix))]
+fn utc_offset(_secs: i64) -> i64 {
+    0
+}
+
+/// Converts days since 1970-01-01 into a (year, month, day) civil date,
+/// following Howard Hinnant's `civil_from_days`.
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+/// `rwxr-xr-x`-style permission bits.
+#[cfg(unix)]
+pub fn permissions(metadata: &Metadata) -> String {
+    use std::os::unix::fs::PermissionsExt;
+
+    let mode = metadata.permissions().mode();
+    let mut out = String::with_capacity(9);
+    for shift in [6, 3, 0] {
+        let bits = mode >> shift;
+        out.push(if bits & 4 != 0 { 'r' } else { '-' });
+        out.push(if bits & 2 != 0 { 'w' } else { '-' });
+        out.push(if bits & 1 != 0 { 'x' } else { '-' });
+    }
+    out
+}
+
+#[cfg(not(unix))]
+pub fn permissions(metadata: &Metadata) -> String {
+    if metadata.permissions().readonly() {
+        "read-only".to_string()
+    } else {
+        "writable".to_string()
+    }
+}
+
+/// Disk space actually allocated to the file, which is less than its length
+/// for sparse files and on compressing filesystems.
+#[cfg(unix)]
+pub fn allocated_size(metadata: &Metadata) -> Option<u64> {
+    use std::os::unix::fs::MetadataExt;
+
+    // `st_blocks` is always in 512-byte units, whatever the block size.
+    Some(metadata.blocks() * 512)
+}
+
+#[cfg(not(unix))]
+pub fn allocated_size(_metadata: &Metadata) -> Option<u64> {
+    None
+}
@@ -0,0 +1,66 @@
+//! Subsequence matching for the `/` filter.
+
+/// Finds `query` in `name` as a subsequence, returning the char indices of
+/// `name` it matched at. Matching ignores case unless `query` has an
+/// uppercase letter in it. Each query character takes the earliest
+/// possible position, which is enough for filtering.
+pub fn match_indices(query: &str, name: &str) -> Option<Vec<usize>> {
+    let case_sensitive = query.chars().any(char::is_uppercase);
+    let fold = |c: char| {
+        if case_sensitive {
+            c
+        } else {
+            c.to_lowercase().next().unwrap_or(c)
+        }
+    };
+    let mut indices = Vec::with_capacity(query.len());
+    let mut name_chars = name.chars().enumerate();
+    for q in query.chars().map(fold) {
+        let (index, _) = name_chars.find(|(_, c)| fold(*c) == q)?;
+        indices.push(index);
+    }
+    Some(indices)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_a_scattered_subsequence() {
+        assert_eq!(match_indices("mrs", "main.rs"), Some(vec![0, 5, 6]));
+        assert_eq!(match_indices("ain", "main.rs"), Some(vec![1, 2, 3]));
+        assert_eq!(match_indices("srm", "main.rs"), None);
+    }
+
+    #[test]
+    fn empty_query_matches_everything() {
+        assert_eq!(match_indices("", "main.rs"), Some(vec![]));
+        assert_eq!(match_indices("", ""), Some(vec![]));
+    }
+
+    #[test]
+    fn query_longer_than_the_name() {
+        assert_eq!(match_indices("mains", "main"), None);
+        assert_eq!(match_indices("a", ""), None);
+    }
+
+    #[test]
+    fn uppercase_query_turns_case_sensitive() {
+        assert_eq!(
+            match_indices("readme", "README.md"),
+            Some(vec![0, 1, 2, 3, 4, 5])
+        );
+        assert_eq!(match_indices("RM", "README.md"), Some(vec![0, 4]));
+        assert_eq!(match_indices("Rm", "README.md"), Some(vec![0, 7]));
+        assert_eq!(match_indices("rM", "README.md"), None);
+    }
+
+    #[test]
+    fn indices_count_chars_not_bytes() {
+        assert_eq!(match_indices("ête", "fête.txt"), Some(vec![1, 2, 3]));
+        assert_eq!(match_indices("ÉT", "ÉTÉ"), Some(vec![0, 1]));
+        assert_eq!(match_indices("été", "ÉTÉ"), Some(vec![0, 1, 2]));
+        assert_eq!(match_indices("日記", "日本の記録"), Some(vec![0, 3]));
+    }
+}
@@ -0,0 +1,148 @@
+//! Just enough knowledge of git repositories to name the checked-out
+//! branch, read straight from `.git/HEAD`, and to mark changed and ignored
+//! files, which is left to `git` itself.
+
+use std::{
+    collections::{HashMap, HashSet},
+    ffi::OsString,
+    fs,
+    path::Path,
+    process::{Command, Stdio},
+};
+
+/// The branch checked out in the repository containing `dir`, or the short
+/// commit hash when `HEAD` is detached. `None` outside a repository.
+pub fn current_branch(dir: &Path) -> Option<String> {
+    let dot_git = dir
+        .ancestors()
+        .map(|d| d.join(".git"))
+        .find(|p| p.exists())?;
+    // Worktrees and submodules have a `.git` file pointing at the real one.
+    let git_dir = if dot_git.is_file() {
+        let content = fs::read_to_string(&dot_git).ok()?;
+        let target = content.trim().strip_prefix("gitdir:")?.trim();
+        dot_git.parent()?.join(target)
+    } else {
+        dot_git
+    };
+    let head = fs::read_to_string(git_dir.join("HEAD")).ok()?;
+    let head = head.trim();
+    match head.strip_prefix("ref:") {
+        Some(reference) => {
+            let reference = reference.trim();
+            Some(
+                reference
+                    .strip_prefix("refs/heads/")
+                    .unwrap_or(reference)
+                    .to_string(),
+            )
+        }
+        None => Some(head.chars().take(7).collect()),
+    }
+}
+
+/// How an entry differs from what is committed, in increasing order of
+/// importance: a directory shows the most important status inside it.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Status {
+    Untracked,
+    /// Changes added to the index and nothing more.
+    Staged,
+    /// Changes in the working tree, staged or not, and merge conflicts.
+    Modified,
+}
+
+/// The status of every changed entry directly in `dir`, keyed by file name.
+/// `None` outside a repository or if git can't be run.
+pub fn statuses(dir: &Path) -> Option<HashMap<OsString, Status>> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(["status", "--porcelain=v1", "-z", "--", "."])
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    // Paths come relative to the top of the repository.
+    let prefix = Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(["rev-parse", "--show-prefix"])
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output()
+        .ok()?;
+    let prefix = String::from_utf8_lossy(&prefix.stdout)
+        .trim_end()
+        .to_string();
+
+    let mut statuses = HashMap::new();
+    let mut records = output.stdout.split(|&b| b == 0);
+    while let Some(record) = records.next() {
+        let (Some(&x), Some(&y), Some(path)) = (record.first(), record.get(1), record.get(3..))
+        else {
+            continue;
+        };
+        // Renames and copies are followed by the path they came from.
+        if matches!(x, b'R' | b'C') {
+            records.next();
+        }
+        let status = match (x, y) {
+            (b'?', b'?') => Status::Untracked,
+            (_, b' ') => Status::Staged,
+            _ => Status::Modified,
+        };
+        let path = String::from_utf8_lossy(path);
+        let Some(name) = path
+            .strip_prefix(&prefix)
+            .and_then(|rest| rest.split('/').find(|c| !c.is_empty()))
+        else {
+            continue;
+        };
+        let slot = statuses.entry(OsString::from(name)).or_insert(status);
+        *slot = (*slot).max(status);
+    }
+    Some(statuses)
+}
+
+/// The names of the entries directly in `dir` that the repository ignores.
+/// `None` outside a repository or if git can't be run.
+pub fn ignored(dir: &Path) -> Option<HashSet<OsString>> {
+    // `--directory` names an ignored directory once instead of everything
+    // in it, which matters for `target/` and `node_modules/`.
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args([
+            "ls-files",
+            "-z",
+            "--others",
+            "--ignored",
+            "--exclude-standard",
+            "--directory",
+            "--",
+            ".",
+        ])
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    // Paths come relative to `dir`; deeper ones belong to subdirectories
+    // that are not ignored as a whole.
+    Some(
+        output
+            .stdout
+            .split(|&b| b == 0)
+            .map(String::from_utf8_lossy)
+            .map(|path| path.trim_end_matches('/').to_string())
+            .filter(|path| !path.is_empty() && !path.contains('/'))
+            .map(OsString::from)
+            .collect(),
+    )
+}
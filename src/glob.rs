@@ -0,0 +1,87 @@
+//! Minimal shell-style glob matching for path patterns from the config.
+//!
+//! `*` matches within one path component, `**` across components, and `?`
+//! a single character. A pattern without a `/` is matched against the file
+//! name alone; one with a `/` against the trailing components of the path.
+
+use std::path::Path;
+
+fn matches(pattern: &[char], text: &[char]) -> bool {
+    match pattern.split_first() {
+        None => text.is_empty(),
+        Some(('*', rest)) if rest.first() == Some(&'*') => {
+            let rest = &rest[1..];
+            match rest.strip_prefix(&['/']) {
+                // `**/` stands for whole components, so what follows it
+                // starts a component too.
+                Some(rest) => (0..=text.len())
+                    .filter(|&i| i == 0 || text[i - 1] == '/')
+                    .any(|i| matches(rest, &text[i..])),
+                None => (0..=text.len()).any(|i| matches(rest, &text[i..])),
+            }
+        }
+        Some(('*', rest)) => {
+            let limit = text.iter().position(|&c| c == '/').unwrap_or(text.len());
+            (0..=limit).any(|i| matches(rest, &text[i..]))
+        }
+        Some(('?', rest)) => text
+            .split_first()
+            .is_some_and(|(&c, text)| c != '/' && matches(rest, text)),
+        Some((&p, rest)) => text
+            .split_first()
+            .is_some_and(|(&c, text)| c == p && matches(rest, text)),
+    }
+}
+
+/// Whether `path` matches `pattern`.
+pub fn is_match(pattern: &str, path: &Path) -> bool {
+    if pattern.contains('/') {
+        let pattern: Vec<char> = format!("**/{}", pattern.trim_start_matches('/'))
+            .chars()
+            .collect();
+        let text: Vec<char> = path.to_string_lossy().chars().collect();
+        matches(&pattern, &text)
+    } else {
+        let Some(name) = path.file_name() else {
+            return false;
+        };
+        let pattern: Vec<char> = pattern.chars().collect();
+        let text: Vec<char> = name.to_string_lossy().chars().collect();
+        matches(&pattern, &text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn name_patterns_match_the_file_name() {
+        assert!(is_match("*.min.js", Path::new("/src/app.min.js")));
+        assert!(is_match("yarn.lock", Path::new("/a/yarn.lock")));
+        assert!(!is_match("*.js", Path::new("/src/app.jsx")));
+        assert!(is_match("?.rs", Path::new("/src/a.rs")));
+        assert!(!is_match("?.rs", Path::new("/src/ab.rs")));
+    }
+
+    #[test]
+    fn star_stays_within_a_component() {
+        assert!(is_match("dist/*.js", Path::new("/src/dist/a.js")));
+        assert!(!is_match("dist/*.js", Path::new("/src/dist/sub/a.js")));
+    }
+
+    #[test]
+    fn double_star_crosses_components() {
+        assert!(is_match("dist/**/*.js", Path::new("/dist/a/b/c.js")));
+        assert!(is_match("dist/**/*.js", Path::new("/dist/c.js")));
+        assert!(is_match("dist/**", Path::new("/dist/a/b")));
+    }
+
+    #[test]
+    fn components_are_matched_whole() {
+        assert!(!is_match("dist/*.js", Path::new("/src/mydist/a.js")));
+        assert!(!is_match("**/foo/*.js", Path::new("/barfoo/a.js")));
+        assert!(is_match("**/foo/*.js", Path::new("/bar/foo/a.js")));
+        assert!(!is_match("a/**/b", Path::new("/a/xb")));
+    }
+}
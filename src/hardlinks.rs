@@ -0,0 +1,81 @@
+//! Hard links: how many names a file has, and where the others are.
+
+use std::{
+    fs::Metadata,
+    io,
+    path::{Path, PathBuf},
+};
+
+/// What a search for a file's other names turned up.
+pub struct Scan {
+    pub paths: Vec<PathBuf>,
+    /// False when the entry limit cut the search short.
+    pub complete: bool,
+}
+
+#[cfg(unix)]
+pub fn link_count(metadata: &Metadata) -> u64 {
+    use std::os::unix::fs::MetadataExt;
+
+    metadata.nlink()
+}
+
+#[cfg(not(unix))]
+pub fn link_count(_metadata: &Metadata) -> u64 {
+    1
+}
+
+/// Walks `root` for other paths to the same inode as `target`, looking at
+/// no more than `limit` entries. Only directories on `target`'s filesystem
+/// are entered, since a hard link can't cross filesystems.
+#[cfg(unix)]
+pub fn find_links(target: &Path, root: &Path, limit: usize) -> io::Result<Scan> {
+    use std::{fs, os::unix::fs::MetadataExt};
+
+    let metadata = fs::symlink_metadata(target)?;
+    let (dev, ino) = (metadata.dev(), metadata.ino());
+    let mut paths = Vec::new();
+    let mut seen = 0;
+    let mut dirs = vec![root.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        let Ok(entries) = fs::read_dir(&dir) else {
+            continue;
+        };
+        for entry in entries.filter_map(|e| e.ok()) {
+            seen += 1;
+            if seen > limit {
+                return Ok(Scan {
+                    paths,
+                    complete: false,
+                });
+            }
+            // `DirEntry::metadata` doesn't follow symlinks, so they are
+            // neither descended into nor mistaken for links.
+            let Ok(metadata) = entry.metadata() else {
+                continue;
+            };
+            if metadata.dev() != dev {
+                continue;
+            }
+            let path = entry.path();
+            if metadata.is_dir() {
+                dirs.push(path);
+            } else if metadata.ino() == ino && path != target {
+                paths.push(path);
+            }
+        }
+    }
+    paths.sort();
+    Ok(Scan {
+        paths,
+        complete: true,
+    })
+}
+
+#[cfg(not(unix))]
+pub fn find_links(_target: &Path, _root: &Path, _limit: usize) -> io::Result<Scan> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "hard links can't be told apart on this platform",
+    ))
+}
@@ -0,0 +1,58 @@
+//! Classic `offset  hex bytes  ascii` dumps for previewing binary files.
+
+use ratatui::{
+    style::{Color, Style},
+    text::{Line, Span},
+};
+
+const BYTES_PER_ROW: usize = 16;
+
+/// Dumps `bytes`, the start of a file `total` bytes long, noting at the end
+/// if that was only part of it.
+pub fn render(bytes: &[u8], total: u64) -> Vec<Line<'static>> {
+    let dim = Style::default().fg(Color::DarkGray);
+    let mut lines: Vec<Line> = bytes
+        .chunks(BYTES_PER_ROW)
+        .enumerate()
+        .map(|(row, chunk)| {
+            let mut hex = String::with_capacity(3 * BYTES_PER_ROW + 1);
+            for i in 0..BYTES_PER_ROW {
+                if i == BYTES_PER_ROW / 2 {
+                    hex.push(' ');
+                }
+                match chunk.get(i) {
+                    Some(b) => hex.push_str(&format!("{:02x} ", b)),
+                    None => hex.push_str("   "),
+                }
+            }
+            let mut spans = vec![
+                Span::styled(format!("{:08x}  ", row * BYTES_PER_ROW), dim),
+                Span::raw(hex),
+                Span::raw(" "),
+            ];
+            // Printable runs stay plain; everything else is a dim dot.
+            let mut ascii: Vec<Span> = Vec::new();
+            for &b in chunk {
+                let printable = b.is_ascii_graphic() || b == b' ';
+                let (c, style) = if printable {
+                    (b as char, Style::default())
+                } else {
+                    ('.', dim)
+                };
+                match ascii.last_mut() {
+                    Some(last) if last.style == style => last.content.to_mut().push(c),
+                    _ => ascii.push(Span::styled(c.to_string(), style)),
+                }
+            }
+            spans.extend(ascii);
+            Line::from(spans)
+        })
+        .collect();
+    if (bytes.len() as u64) < total {
+        lines.push(Line::from(Span::styled(
+            format!("... first {} of {} bytes", bytes.len(), total),
+            dim,
+        )));
+    }
+    lines
+}
@@ -0,0 +1,52 @@
+//! Nerd Font glyphs for entries, by type and extension. They only show up
+//! right with a patched font, so `list.icons` turns them on.
+
+use std::{ffi::OsStr, path::Path};
+
+const DIRECTORY: &str = "\u{f07b}";
+const FILE: &str = "\u{f15b}";
+
+/// Extensions, lowercase, and the glyph files carrying them get.
+const BY_EXTENSION: &[(&[&str], &str)] = &[
+    (&["rs"], "\u{e7a8}"),
+    (&["md", "markdown"], "\u{e73e}"),
+    (&["py"], "\u{e73c}"),
+    (&["js", "mjs", "cjs"], "\u{e74e}"),
+    (&["ts", "tsx"], "\u{e628}"),
+    (&["go"], "\u{e626}"),
+    (&["c", "h"], "\u{e61e}"),
+    (&["cpp", "cc", "hpp"], "\u{e61d}"),
+    (&["html", "htm"], "\u{e736}"),
+    (&["css"], "\u{e749}"),
+    (&["json"], "\u{e60b}"),
+    (&["toml", "yaml", "yml", "ini", "conf"], "\u{e615}"),
+    (&["sh", "bash", "zsh", "fish"], "\u{f489}"),
+    (&["txt", "log"], "\u{f15c}"),
+    (&["pdf"], "\u{f1c1}"),
+    (
+        &["png", "jpg", "jpeg", "gif", "bmp", "svg", "webp", "ico"],
+        "\u{f1c5}",
+    ),
+    (&["mp3", "flac", "wav", "ogg", "m4a"], "\u{f1c7}"),
+    (&["mp4", "mkv", "webm", "mov", "avi"], "\u{f1c8}"),
+    (
+        &["zip", "tar", "gz", "tgz", "xz", "bz2", "zst", "7z", "rar"],
+        "\u{f410}",
+    ),
+    (&["lock"], "\u{f023}"),
+];
+
+/// The glyph for an entry called `name`, one cell wide.
+pub fn icon(name: &OsStr, is_dir: bool) -> &'static str {
+    if is_dir {
+        return DIRECTORY;
+    }
+    let Some(extension) = Path::new(name).extension().and_then(|e| e.to_str()) else {
+        return FILE;
+    };
+    let extension = extension.to_ascii_lowercase();
+    BY_EXTENSION
+        .iter()
+        .find(|(extensions, _)| extensions.contains(&extension.as_str()))
+        .map_or(FILE, |(_, glyph)| *glyph)
+}
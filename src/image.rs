@@ -0,0 +1,590 @@
+//! Pictures in the preview pane: PNG, JPEG and BMP files decoded here and
+//! drawn with half blocks, two pixels to a cell, scaled down to fit the
+//! pane.
+
+mod jpeg;
+
+use std::{fs, path::Path};
+
+use ratatui::{
+    style::{Color, Style},
+    text::{Line, Span},
+};
+
+const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+
+/// A start-of-image marker and the start of the next marker.
+const JPEG_SIGNATURE: &[u8] = b"\xff\xd8\xff";
+
+/// Files bigger than this are not worth decoding for a preview.
+const MAX_FILE_BYTES: u64 = 16 * 1024 * 1024;
+
+/// Nor are images with more pixels than this, whatever they compress to.
+const MAX_PIXELS: usize = 16 * 1024 * 1024;
+
+/// A decoded image, row by row, as RGBA.
+pub struct Image {
+    format: &'static str,
+    width: usize,
+    height: usize,
+    pixels: Vec<[u8; 4]>,
+}
+
+/// Whether `head`, the start of a file, looks like an image this module
+/// can draw.
+pub fn is_image(head: &[u8]) -> bool {
+    head.starts_with(PNG_SIGNATURE) || head.starts_with(JPEG_SIGNATURE) || head.starts_with(b"BM")
+}
+
+/// Reads and decodes the image at `path`. `None` if it is too big or
+/// isn't a PNG, JPEG or BMP this module understands.
+pub fn decode(path: &Path) -> Option<Image> {
+    if fs::metadata(path).ok()?.len() > MAX_FILE_BYTES {
+        return None;
+    }
+    let bytes = fs::read(path).ok()?;
+    if bytes.starts_with(PNG_SIGNATURE) {
+        decode_png(&bytes)
+    } else if bytes.starts_with(JPEG_SIGNATURE) {
+        jpeg::decode(&bytes)
+    } else if bytes.starts_with(b"BM") {
+        decode_bmp(&bytes)
+    } else {
+        None
+    }
+}
+
+/// Draws `image` in at most `columns` x `rows` cells, under a line naming
+/// its format and size.
+pub fn draw(image: &Image, columns: u16, rows: u16) -> Vec<Line<'static>> {
+    let mut lines = vec![Line::from(Span::styled(
+        format!("{} image, {} x {}", image.format, image.width, image.height),
+        Style::default().fg(Color::DarkGray),
+    ))];
+    lines.extend(half_blocks(image, columns, rows.saturating_sub(1)));
+    lines
+}
+
+/// Scales `image` down to fit `columns` x `rows` cells, keeping its aspect,
+/// and draws each cell as `▀`: the upper pixel in front, the lower behind.
+fn half_blocks(image: &Image, columns: u16, rows: u16) -> Vec<Line<'static>> {
+    let (max_width, max_height) = (usize::from(columns.max(1)), usize::from(rows.max(1)) * 2);
+    let scale = (max_width as f64 / image.width as f64)
+        .min(max_height as f64 / image.height as f64)
+        .min(1.0);
+    let width = ((image.width as f64 * scale).round() as usize).clamp(1, max_width);
+    let height = ((image.height as f64 * scale).round() as usize).clamp(1, max_height);
+    let scaled = resize(image, width, height);
+    // Mostly transparent pixels show the terminal's own background.
+    let color =
+        |pixel: [u8; 4]| (pixel[3] >= 128).then(|| Color::Rgb(pixel[0], pixel[1], pixel[2]));
+    (0..height)
+        .step_by(2)
+        .map(|y| {
+            let spans: Vec<Span> = (0..width)
+                .map(|x| {
+                    let upper = color(scaled[y * width + x]);
+                    let lower = (y + 1 < height)
+                        .then(|| color(scaled[(y + 1) * width + x]))
+                        .flatten();
+                    match (upper, lower) {
+                        (Some(upper), Some(lower)) => {
+                            Span::styled("▀", Style::default().fg(upper).bg(lower))
+                        }
+                        (Some(upper), None) => Span::styled("▀", Style::default().fg(upper)),
+                        (None, Some(lower)) => Span::styled("▄", Style::default().fg(lower)),
+                        (None, None) => Span::raw(" "),
+                    }
+                })
+                .collect();
+            Line::from(spans)
+        })
+        .collect()
+}
+
+/// Shrinks `image` to `width` x `height` by averaging the pixels each
+/// target pixel covers, weighting colors by their opacity.
+fn resize(image: &Image, width: usize, height: usize) -> Vec<[u8; 4]> {
+    let mut scaled = Vec::with_capacity(width * height);
+    for y in 0..height {
+        let (top, bottom) = (
+            y * image.height / height,
+            ((y + 1) * image.height / height).max(y * image.height / height + 1),
+        );
+        for x in 0..width {
+            let (left, right) = (
+                x * image.width / width,
+                ((x + 1) * image.width / width).max(x * image.width / width + 1),
+            );
+            let mut sum = [0u64; 4];
+            for row in top..bottom {
+                for pixel in &image.pixels[row * image.width + left..row * image.width + right] {
+                    let alpha = u64::from(pixel[3]);
+                    for channel in 0..3 {
+                        sum[channel] += u64::from(pixel[channel]) * alpha;
+                    }
+                    sum[3] += alpha;
+                }
+            }
+            let count = ((bottom - top) * (right - left)) as u64;
+            // Fully transparent areas come out black, which is never seen.
+            let average = |channel: usize| sum[channel].checked_div(sum[3]).unwrap_or(0) as u8;
+            scaled.push([average(0), average(1), average(2), (sum[3] / count) as u8]);
+        }
+    }
+    scaled
+}
+
+fn be_u32(bytes: &[u8], at: usize) -> Option<u32> {
+    Some(u32::from_be_bytes(bytes.get(at..at + 4)?.try_into().ok()?))
+}
+
+fn le_u16(bytes: &[u8], at: usize) -> Option<u16> {
+    Some(u16::from_le_bytes(bytes.get(at..at + 2)?.try_into().ok()?))
+}
+
+fn le_u32(bytes: &[u8], at: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(bytes.get(at..at + 4)?.try_into().ok()?))
+}
+
+/// How a PNG stores its pixels, from the `IHDR` chunk and friends.
+struct PngFormat {
+    bit_depth: u8,
+    color_type: u8,
+    palette: Vec<[u8; 4]>,
+    /// The one color shown as transparent, for grayscale and RGB images,
+    /// as samples at the image's own bit depth.
+    transparent: Option<[u16; 3]>,
+}
+
+impl PngFormat {
+    fn channels(&self) -> usize {
+        match self.color_type {
+            0 | 3 => 1,
+            2 => 3,
+            4 => 2,
+            _ => 4,
+        }
+    }
+
+    fn bits_per_pixel(&self) -> usize {
+        self.channels() * usize::from(self.bit_depth)
+    }
+
+    /// Reads the pixels of one reconstructed scanline.
+    fn pixels(&self, row: &[u8], width: usize, out: &mut Vec<[u8; 4]>) -> Option<()> {
+        let depth = usize::from(self.bit_depth);
+        let sample = |index: usize| -> u16 {
+            match depth {
+                8 => u16::from(row[index]),
+                16 => u16::from_be_bytes([row[2 * index], row[2 * index + 1]]),
+                _ => {
+                    let bit = index * depth;
+                    u16::from(row[bit / 8] >> (8 - depth - bit % 8)) & ((1 << depth) - 1)
+                }
+            }
+        };
+        // Samples scaled to eight bits.
+        let max = (1u32 << depth) - 1;
+        let eight = |value: u16| (u32::from(value) * 255 / max) as u8;
+        let channels = self.channels();
+        for x in 0..width {
+            let at = x * channels;
+            let pixel = match self.color_type {
+                0 => {
+                    let gray = sample(at);
+                    let alpha = if self.transparent.is_some_and(|t| t[0] == gray) {
+                        0
+                    } else {
+                        255
+                    };
+                    let gray = eight(gray);
+                    [gray, gray, gray, alpha]
+                }
+                2 => {
+                    let rgb = [sample(at), sample(at + 1), sample(at + 2)];
+                    let alpha = if self.transparent == Some(rgb) {
+                        0
+                    } else {
+                        255
+                    };
+                    [eight(rgb[0]), eight(rgb[1]), eight(rgb[2]), alpha]
+                }
+                3 => *self.palette.get(usize::from(sample(at)))?,
+                4 => {
+                    let gray = eight(sample(at));
+                    [gray, gray, gray, eight(sample(at + 1))]
+                }
+                _ => [
+                    eight(sample(at)),
+                    eight(sample(at + 1)),
+                    eight(sample(at + 2)),
+                    eight(sample(at + 3)),
+                ],
+            };
+            out.push(pixel);
+        }
+        Some(())
+    }
+}
+
+fn decode_png(bytes: &[u8]) -> Option<Image> {
+    let mut at = PNG_SIGNATURE.len();
+    let mut header = None;
+    let mut palette = Vec::new();
+    let mut transparency = None;
+    let mut data = Vec::new();
+    while at + 8 <= bytes.len() {
+        let length = be_u32(bytes, at)? as usize;
+        let kind = bytes.get(at + 4..at + 8)?;
+        let body = bytes.get(at + 8..at + 8 + length)?;
+        match kind {
+            b"IHDR" => header = Some(body),
+            b"PLTE" => {
+                palette = body
+                    .chunks_exact(3)
+                    .map(|c| [c[0], c[1], c[2], 255])
+                    .collect()
+            }
+            b"tRNS" => transparency = Some(body),
+            b"IDAT" => data.extend_from_slice(body),
+            b"IEND" => break,
+            _ => {}
+        }
+        // Length, type, body and CRC.
+        at += 12 + length;
+    }
+    let header = header?;
+    let width = be_u32(header, 0)? as usize;
+    let height = be_u32(header, 4)? as usize;
+    let (&bit_depth, &color_type, &interlace) = (header.get(8)?, header.get(9)?, header.get(12)?);
+    let depth_allowed: &[u8] = match color_type {
+        0 => &[1, 2, 4, 8, 16],
+        3 => &[1, 2, 4, 8],
+        2 | 4 | 6 => &[8, 16],
+        _ => return None,
+    };
+    if width == 0
+        || height == 0
+        || width.checked_mul(height)? > MAX_PIXELS
+        || !depth_allowed.contains(&bit_depth)
+        || (color_type == 3 && palette.is_empty())
+    {
+        return None;
+    }
+    let mut transparent = None;
+    if let Some(t) = transparency {
+        match color_type {
+            3 => {
+                for (entry, &alpha) in palette.iter_mut().zip(t) {
+                    entry[3] = alpha;
+                }
+            }
+            0 => transparent = Some([u16::from_be_bytes(t.get(0..2)?.try_into().ok()?); 3]),
+            2 => {
+                let sample = |i: usize| -> Option<u16> {
+                    Some(u16::from_be_bytes(
+                        t.get(2 * i..2 * i + 2)?.try_into().ok()?,
+                    ))
+                };
+                transparent = Some([sample(0)?, sample(1)?, sample(2)?]);
+            }
+            _ => {}
+        }
+    }
+    let format = PngFormat {
+        bit_depth,
+        color_type,
+        palette,
+        transparent,
+    };
+
+    // Adam7 passes as (x, y) start and step; one pass covering everything
+    // otherwise.
+    let passes: &[(usize, usize, usize, usize)] = if interlace == 1 {
+        &[
+            (0, 0, 8, 8),
+            (4, 0, 8, 8),
+            (0, 4, 4, 8),
+            (2, 0, 4, 4),
+            (0, 2, 2, 4),
+            (1, 0, 2, 2),
+            (0, 1, 1, 2),
+        ]
+    } else {
+        &[(0, 0, 1, 1)]
+    };
+    let bits = format.bits_per_pixel();
+    let stride = |pass_width: usize| (pass_width * bits).div_ceil(8);
+    let expected: usize = passes
+        .iter()
+        .map(|&(x0, y0, dx, dy)| {
+            let (w, h) = (
+                width.saturating_sub(x0).div_ceil(dx),
+                height.saturating_sub(y0).div_ceil(dy),
+            );
+            if w == 0 {
+                0
+            } else {
+                h * (stride(w) + 1)
+            }
+        })
+        .sum();
+    let raw = miniz_oxide::inflate::decompress_to_vec_zlib_with_limit(&data, expected).ok()?;
+
+    let mut pixels = vec![[0u8; 4]; width * height];
+    let mut raw = &raw[..];
+    let filter_bytes = bits.div_ceil(8);
+    for &(x0, y0, dx, dy) in passes {
+        let pass_width = width.saturating_sub(x0).div_ceil(dx);
+        let pass_height = height.saturating_sub(y0).div_ceil(dy);
+        if pass_width == 0 || pass_height == 0 {
+            continue;
+        }
+        let stride = stride(pass_width);
+        let mut previous = vec![0u8; stride];
+        let mut row_pixels = Vec::with_capacity(pass_width);
+        for pass_y in 0..pass_height {
+            let (filter, rest) = raw.split_first()?;
+            let mut row = rest.get(..stride)?.to_vec();
+            raw = &rest[stride..];
+            unfilter(*filter, &mut row, &previous, filter_bytes)?;
+            row_pixels.clear();
+            format.pixels(&row, pass_width, &mut row_pixels)?;
+            let y = y0 + pass_y * dy;
+            for (pass_x, pixel) in row_pixels.iter().enumerate() {
+                pixels[y * width + x0 + pass_x * dx] = *pixel;
+            }
+            previous = row;
+        }
+    }
+    Some(Image {
+        format: "PNG",
+        width,
+        height,
+        pixels,
+    })
+}
+
+/// Undoes a scanline's filter in place, given the reconstructed line above
+/// and the bytes per pixel, at least one.
+fn unfilter(filter: u8, row: &mut [u8], previous: &[u8], bpp: usize) -> Option<()> {
+    for i in 0..row.len() {
+        let left = if i >= bpp { row[i - bpp] } else { 0 };
+        let up = previous[i];
+        let up_left = if i >= bpp { previous[i - bpp] } else { 0 };
+        let predicted = match filter {
+            0 => 0,
+            1 => left,
+            2 => up,
+            3 => ((u16::from(left) + u16::from(up)) / 2) as u8,
+            4 => {
+                let p = i16::from(left) + i16::from(up) - i16::from(up_left);
+                let (pa, pb, pc) = (
+                    (p - i16::from(left)).abs(),
+                    (p - i16::from(up)).abs(),
+                    (p - i16::from(up_left)).abs(),
+                );
+                if pa <= pb && pa <= pc {
+                    left
+                } else if pb <= pc {
+                    up
+                } else {
+                    up_left
+                }
+            }
+            _ => return None,
+        };
+        row[i] = row[i].wrapping_add(predicted);
+    }
+    Some(())
+}
+
+/// Uncompressed 24- and 32-bit BMPs, which covers most that turn up.
+fn decode_bmp(bytes: &[u8]) -> Option<Image> {
+    let offset = le_u32(bytes, 10)? as usize;
+    let width = le_u32(bytes, 18)? as i32;
+    let height = le_u32(bytes, 22)? as i32;
+    let bits = le_u16(bytes, 28)?;
+    let compression = le_u32(bytes, 30)?;
+    // 3 is bitfields, which for 32-bit files is nearly always plain BGRA.
+    if width <= 0 || height == 0 || !matches!((bits, compression), (24, 0) | (32, 0) | (32, 3)) {
+        return None;
+    }
+    let (width, top_down) = (width as usize, height < 0);
+    let height = height.unsigned_abs() as usize;
+    if width.checked_mul(height)? > MAX_PIXELS {
+        return None;
+    }
+    let bytes_per_pixel = usize::from(bits / 8);
+    let stride = (width * bytes_per_pixel).div_ceil(4) * 4;
+    let mut pixels = Vec::with_capacity(width * height);
+    for y in 0..height {
+        let row = if top_down { y } else { height - 1 - y };
+        let start = offset + row * stride;
+        let row = bytes.get(start..start + width * bytes_per_pixel)?;
+        // The alpha byte of 32-bit files is as often junk as not.
+        pixels.extend(
+            row.chunks_exact(bytes_per_pixel)
+                .map(|bgr| [bgr[2], bgr[1], bgr[0], 255]),
+        );
+    }
+    Some(Image {
+        format: "BMP",
+        width,
+        height,
+        pixels,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A PNG of the given header fields and extra chunks, around `raw`,
+    /// the filtered scanlines. The decoder doesn't check CRCs.
+    fn png(header: (u32, u32, u8, u8, u8), chunks: &[(&[u8], &[u8])], raw: &[u8]) -> Vec<u8> {
+        let (width, height, bit_depth, color_type, interlace) = header;
+        let mut ihdr = [width.to_be_bytes(), height.to_be_bytes()].concat();
+        ihdr.extend([bit_depth, color_type, 0, 0, interlace]);
+        let data = miniz_oxide::deflate::compress_to_vec_zlib(raw, 6);
+        let mut bytes = PNG_SIGNATURE.to_vec();
+        let chunks = [(&b"IHDR"[..], &ihdr[..])]
+            .into_iter()
+            .chain(chunks.iter().copied())
+            .chain([(&b"IDAT"[..], &data[..]), (&b"IEND"[..], &[][..])]);
+        for (kind, body) in chunks {
+            bytes.extend((body.len() as u32).to_be_bytes());
+            bytes.extend(kind);
+            bytes.extend(body);
+            bytes.extend([0; 4]);
+        }
+        bytes
+    }
+
+    fn grays(image: &Image) -> Vec<u8> {
+        image.pixels.iter().map(|p| p[0]).collect()
+    }
+
+    #[test]
+    fn png_filters() {
+        #[rustfmt::skip]
+        let raw = [
+            1, 10, 5, 5, // Sub
+            2, 1, 1, 1, // Up
+            3, 0, 0, 0, // Average
+            4, 1, 0, 2, // Paeth
+        ];
+        let image = decode_png(&png((3, 4, 8, 0, 0), &[], &raw)).unwrap();
+        assert_eq!(
+            grays(&image),
+            [10, 15, 20, 11, 16, 21, 5, 10, 15, 6, 10, 17]
+        );
+        assert!(image.pixels.iter().all(|p| p[3] == 255));
+    }
+
+    #[test]
+    fn png_adam7() {
+        // A 3 x 3 image whose pixel at (x, y) is 10 * (3y + x), pass by
+        // pass; the second and third passes are empty at this size.
+        #[rustfmt::skip]
+        let raw = [
+            0, 0, // pass 1: (0, 0)
+            0, 20, // pass 4: (2, 0)
+            0, 60, 80, // pass 5: (0, 2), (2, 2)
+            0, 10, 0, 70, // pass 6: (1, 0), then (1, 2)
+            0, 30, 40, 50, // pass 7: row 1
+        ];
+        let image = decode_png(&png((3, 3, 8, 0, 1), &[], &raw)).unwrap();
+        assert_eq!(grays(&image), [0, 10, 20, 30, 40, 50, 60, 70, 80]);
+    }
+
+    #[test]
+    fn png_palette_and_transparency() {
+        let palette = [255, 0, 0, 0, 255, 0, 0, 0, 255];
+        let chunks: &[(&[u8], &[u8])] = &[(b"PLTE", &palette), (b"tRNS", &[0, 128])];
+        // Two-bit indices 2, 1 and 0, packed into one byte.
+        let image = decode_png(&png((3, 1, 2, 3, 0), chunks, &[0, 0b1001_0000])).unwrap();
+        assert_eq!(
+            image.pixels,
+            [[0, 0, 255, 255], [0, 255, 0, 128], [255, 0, 0, 0]]
+        );
+    }
+
+    #[test]
+    fn png_transparent_color() {
+        // Sixteen-bit RGB, with the second pixel's color marked transparent.
+        let chunks: &[(&[u8], &[u8])] = &[(b"tRNS", &[0, 1, 0, 2, 0, 3])];
+        let raw = [0, 255, 255, 0, 0, 0, 0, 0, 1, 0, 2, 0, 3];
+        let image = decode_png(&png((2, 1, 16, 2, 0), chunks, &raw)).unwrap();
+        assert_eq!(image.pixels, [[255, 0, 0, 255], [0, 0, 0, 0]]);
+    }
+
+    #[test]
+    fn png_rejects_bad_headers() {
+        assert!(decode_png(&png((0, 1, 8, 0, 0), &[], &[0])).is_none());
+        assert!(decode_png(&png((1, 1, 16, 3, 0), &[], &[0, 0, 0])).is_none());
+        // A palette image without a palette.
+        assert!(decode_png(&png((1, 1, 8, 3, 0), &[], &[0, 0])).is_none());
+        // Scanlines cut short.
+        assert!(decode_png(&png((2, 2, 8, 2, 0), &[], &[0; 7])).is_none());
+    }
+
+    /// A 24-bit BMP, 2 x 2, with `rows` as stored: bottom up unless the
+    /// height is negative. Each row is padded to four bytes.
+    fn bmp(height: i32, rows: [[u8; 6]; 2]) -> Vec<u8> {
+        let mut bytes = vec![0; 54];
+        bytes[..2].copy_from_slice(b"BM");
+        bytes[10..14].copy_from_slice(&54u32.to_le_bytes());
+        bytes[14..18].copy_from_slice(&40u32.to_le_bytes());
+        bytes[18..22].copy_from_slice(&2i32.to_le_bytes());
+        bytes[22..26].copy_from_slice(&height.to_le_bytes());
+        bytes[26..28].copy_from_slice(&1u16.to_le_bytes());
+        bytes[28..30].copy_from_slice(&24u16.to_le_bytes());
+        for row in rows {
+            bytes.extend(row);
+            bytes.extend([0, 0]);
+        }
+        bytes
+    }
+
+    #[test]
+    fn bmp_rows_and_orientation() {
+        // Blue, green, then red, white: as BGR.
+        let first = [255, 0, 0, 0, 255, 0];
+        let second = [0, 0, 255, 255, 255, 255];
+        let expected = |top: [[u8; 4]; 2], bottom: [[u8; 4]; 2]| [top, bottom].concat();
+        let (blue, green) = ([0, 0, 255, 255], [0, 255, 0, 255]);
+        let (red, white) = ([255, 0, 0, 255], [255, 255, 255, 255]);
+        let bottom_up = decode_bmp(&bmp(2, [first, second])).unwrap();
+        assert_eq!(bottom_up.pixels, expected([red, white], [blue, green]));
+        let top_down = decode_bmp(&bmp(-2, [first, second])).unwrap();
+        assert_eq!(top_down.pixels, expected([blue, green], [red, white]));
+        // A row cut short.
+        let mut short = bmp(2, [first, second]);
+        short.truncate(short.len() - 3);
+        assert!(decode_bmp(&short).is_none());
+    }
+
+    #[test]
+    fn half_blocks_pair_rows() {
+        let image = Image {
+            format: "PNG",
+            width: 2,
+            height: 2,
+            pixels: vec![
+                [255, 0, 0, 255],
+                [0, 0, 0, 0],
+                [0, 0, 255, 255],
+                [0, 0, 0, 0],
+            ],
+        };
+        let lines = half_blocks(&image, 10, 10);
+        assert_eq!(lines.len(), 1);
+        let spans = &lines[0].spans;
+        assert_eq!(spans[0].content, "▀");
+        assert_eq!(spans[0].style.fg, Some(Color::Rgb(255, 0, 0)));
+        assert_eq!(spans[0].style.bg, Some(Color::Rgb(0, 0, 255)));
+        assert_eq!(spans[1].content, " ");
+    }
+}
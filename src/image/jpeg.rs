@@ -0,0 +1,910 @@
+//! Huffman-coded JPEGs, baseline or progressive, in grayscale or YCbCr
+//! (or plain RGB). Arithmetic coding, 12-bit samples, lossless and
+//! hierarchical files are turned down, and so is CMYK: none of them turn
+//! up much outside print.
+
+use super::{Image, MAX_PIXELS};
+
+/// The block position of each coefficient, in the zigzag order files
+/// store them in.
+const ZIGZAG: [usize; 64] = [
+    0, 1, 8, 16, 9, 2, 3, 10, 17, 24, 32, 25, 18, 11, 4, 5, 12, 19, 26, 33, 40, 48, 41, 34, 27, 20,
+    13, 6, 7, 14, 21, 28, 35, 42, 49, 56, 57, 50, 43, 36, 29, 22, 15, 23, 30, 37, 44, 51, 58, 59,
+    52, 45, 38, 31, 39, 46, 53, 60, 61, 54, 47, 55, 62, 63,
+];
+
+fn be_u16(bytes: &[u8], at: usize) -> Option<u16> {
+    Some(u16::from_be_bytes(bytes.get(at..at + 2)?.try_into().ok()?))
+}
+
+/// A Huffman table from a `DHT` segment, looked up a code length at a time.
+struct Huffman {
+    values: Vec<u8>,
+    /// For each code length, the first code of that length, the index of
+    /// its value, and the last code (-1 for none).
+    lengths: [(i32, usize, i32); 16],
+}
+
+impl Huffman {
+    fn new(counts: &[u8], values: &[u8]) -> Huffman {
+        let mut lengths = [(0, 0, -1); 16];
+        let (mut code, mut index) = (0, 0);
+        for (length, &count) in lengths.iter_mut().zip(counts) {
+            let count = i32::from(count);
+            *length = (code, index, if count > 0 { code + count - 1 } else { -1 });
+            index += count as usize;
+            code = (code + count) << 1;
+        }
+        Huffman {
+            values: values.to_vec(),
+            lengths,
+        }
+    }
+
+    fn decode(&self, bits: &mut Bits) -> Option<u8> {
+        let mut code = 0;
+        for &(first, index, last) in &self.lengths {
+            code = (code << 1) | bits.read(1) as i32;
+            if code <= last {
+                return self.values.get(index + (code - first) as usize).copied();
+            }
+        }
+        None
+    }
+}
+
+/// Reads the entropy-coded data of a scan a few bits at a time, undoing
+/// the zero stuffed after each `FF`. At a marker, or past the end, it
+/// reads zeros.
+struct Bits<'a> {
+    data: &'a [u8],
+    at: usize,
+    buffer: u64,
+    count: u32,
+}
+
+impl Bits<'_> {
+    fn read(&mut self, n: u32) -> u32 {
+        if n == 0 {
+            return 0;
+        }
+        while self.count <= 56 {
+            let byte = match (self.data.get(self.at), self.data.get(self.at + 1)) {
+                (Some(0xFF), Some(0)) => {
+                    self.at += 2;
+                    0xFF
+                }
+                (Some(0xFF), _) | (None, _) => 0,
+                (Some(&byte), _) => {
+                    self.at += 1;
+                    byte
+                }
+            };
+            self.buffer |= u64::from(byte) << (56 - self.count);
+            self.count += 8;
+        }
+        let value = (self.buffer >> (64 - n)) as u32;
+        self.buffer <<= n;
+        self.count -= n;
+        value
+    }
+
+    /// A `value` coded in `n` bits, `n` being its magnitude category.
+    fn receive(&mut self, n: u32) -> i32 {
+        let value = self.read(n) as i32;
+        if n > 0 && value < 1 << (n - 1) {
+            value - (1 << n) + 1
+        } else {
+            value
+        }
+    }
+
+    /// Drops what's left of the interval ending here, and the `RSTn` marker
+    /// after it.
+    fn restart(&mut self) {
+        self.buffer = 0;
+        self.count = 0;
+        while self.at + 1 < self.data.len()
+            && !(self.data[self.at] == 0xFF && (0xD0..=0xD7).contains(&self.data[self.at + 1]))
+        {
+            self.at += 1;
+        }
+        self.at += 2;
+    }
+}
+
+/// One color channel of the frame, and the coefficients decoded for it so
+/// far, 64 to a block in row order.
+struct Component {
+    id: u8,
+    horizontal: usize,
+    vertical: usize,
+    quantization: usize,
+    dc_table: usize,
+    ac_table: usize,
+    /// Blocks across and down, padded out to whole MCUs.
+    blocks_wide: usize,
+    blocks_high: usize,
+    coefficients: Vec<i16>,
+    dc_prediction: i32,
+}
+
+struct Frame {
+    width: usize,
+    height: usize,
+    progressive: bool,
+    components: Vec<Component>,
+    max_horizontal: usize,
+    max_vertical: usize,
+    mcus_wide: usize,
+    mcus_high: usize,
+}
+
+/// Which coefficients a scan carries, and which of their bits.
+#[derive(Clone, Copy)]
+struct Pass {
+    progressive: bool,
+    start: usize,
+    end: usize,
+    /// The bit position sent by the previous scan of these coefficients,
+    /// zero on their first.
+    high: u8,
+    low: u8,
+}
+
+#[derive(Default)]
+struct Decoder {
+    frame: Option<Frame>,
+    quantization: [Option<[u16; 64]>; 4],
+    dc_tables: [Option<Huffman>; 4],
+    ac_tables: [Option<Huffman>; 4],
+    restart_interval: usize,
+    /// The color transform an Adobe `APP14` segment names, if there is one.
+    adobe_transform: Option<u8>,
+    scans: usize,
+}
+
+pub fn decode(bytes: &[u8]) -> Option<Image> {
+    let mut decoder = Decoder::default();
+    let mut at = 2;
+    // A file cut short still shows as much as its scans got through.
+    while let Some(marker) = next_marker(bytes, &mut at) {
+        match marker {
+            0xD9 => break,
+            0x01 | 0xD0..=0xD8 => continue,
+            _ => {}
+        }
+        let length = usize::from(be_u16(bytes, at)?);
+        let segment = bytes.get(at + 2..at + length.max(2))?;
+        at += length;
+        match marker {
+            0xC0 | 0xC1 => decoder.read_frame(segment, false)?,
+            0xC2 => decoder.read_frame(segment, true)?,
+            0xC3 | 0xC5..=0xCB | 0xCD..=0xCF => return None,
+            0xC4 => decoder.read_huffman_tables(segment)?,
+            0xDB => decoder.read_quantization_tables(segment)?,
+            0xDD => decoder.restart_interval = usize::from(be_u16(segment, 0)?),
+            0xEE if segment.starts_with(b"Adobe") => {
+                decoder.adobe_transform = segment.get(11).copied();
+            }
+            0xDA => at = decoder.read_scan(segment, bytes, at)?,
+            _ => {}
+        }
+    }
+    decoder.image()
+}
+
+/// The marker at or after `at`, moving `at` past it. Fill bytes and
+/// anything else before it are skipped.
+fn next_marker(bytes: &[u8], at: &mut usize) -> Option<u8> {
+    loop {
+        while *bytes.get(*at)? != 0xFF {
+            *at += 1;
+        }
+        while *bytes.get(*at)? == 0xFF {
+            *at += 1;
+        }
+        *at += 1;
+        // `FF 00` is an `FF` byte of data, not a marker.
+        if bytes[*at - 1] != 0 {
+            return Some(bytes[*at - 1]);
+        }
+    }
+}
+
+/// Where the entropy-coded data starting at `at` ends: at the first marker
+/// other than a restart.
+fn entropy_end(bytes: &[u8], at: usize) -> usize {
+    (at..bytes.len().saturating_sub(1))
+        .find(|&i| bytes[i] == 0xFF && !matches!(bytes[i + 1], 0 | 0xD0..=0xD7))
+        .unwrap_or(bytes.len())
+}
+
+impl Decoder {
+    fn read_frame(&mut self, segment: &[u8], progressive: bool) -> Option<()> {
+        let precision = *segment.first()?;
+        let height = usize::from(be_u16(segment, 1)?);
+        let width = usize::from(be_u16(segment, 3)?);
+        let count = usize::from(*segment.get(5)?);
+        if self.frame.is_some()
+            || precision != 8
+            || !matches!(count, 1 | 3)
+            || width == 0
+            || height == 0
+            || width * height > MAX_PIXELS
+        {
+            return None;
+        }
+        let mut components = Vec::with_capacity(count);
+        for fields in segment.get(6..6 + 3 * count)?.chunks_exact(3) {
+            let (horizontal, vertical) = (usize::from(fields[1] >> 4), usize::from(fields[1] & 15));
+            if !(1..=4).contains(&horizontal) || !(1..=4).contains(&vertical) || fields[2] > 3 {
+                return None;
+            }
+            components.push(Component {
+                id: fields[0],
+                horizontal,
+                vertical,
+                quantization: usize::from(fields[2]),
+                dc_table: 0,
+                ac_table: 0,
+                blocks_wide: 0,
+                blocks_high: 0,
+                coefficients: Vec::new(),
+                dc_prediction: 0,
+            });
+        }
+        let max_horizontal = components.iter().map(|c| c.horizontal).max()?;
+        let max_vertical = components.iter().map(|c| c.vertical).max()?;
+        let mcus_wide = width.div_ceil(8 * max_horizontal);
+        let mcus_high = height.div_ceil(8 * max_vertical);
+        for component in &mut components {
+            component.blocks_wide = mcus_wide * component.horizontal;
+            component.blocks_high = mcus_high * component.vertical;
+            component.coefficients = vec![0; component.blocks_wide * component.blocks_high * 64];
+        }
+        self.frame = Some(Frame {
+            width,
+            height,
+            progressive,
+            components,
+            max_horizontal,
+            max_vertical,
+            mcus_wide,
+            mcus_high,
+        });
+        Some(())
+    }
+
+    fn read_huffman_tables(&mut self, mut segment: &[u8]) -> Option<()> {
+        while let Some(&kind) = segment.first() {
+            let counts = segment.get(1..17)?;
+            let total = counts.iter().map(|&c| usize::from(c)).sum::<usize>();
+            let values = segment.get(17..17 + total)?;
+            let table = Some(Huffman::new(counts, values));
+            match (kind >> 4, usize::from(kind & 15)) {
+                (0, id @ 0..4) => self.dc_tables[id] = table,
+                (1, id @ 0..4) => self.ac_tables[id] = table,
+                _ => return None,
+            }
+            segment = &segment[17 + total..];
+        }
+        Some(())
+    }
+
+    fn read_quantization_tables(&mut self, mut segment: &[u8]) -> Option<()> {
+        while let Some(&kind) = segment.first() {
+            let (wide, id) = (kind >> 4 != 0, usize::from(kind & 15));
+            let size = if wide { 128 } else { 64 };
+            let values = segment.get(1..1 + size)?;
+            let mut table = [0; 64];
+            for (i, &position) in ZIGZAG.iter().enumerate() {
+                table[position] = if wide {
+                    be_u16(values, 2 * i)?
+                } else {
+                    u16::from(values[i])
+                };
+            }
+            *self.quantization.get_mut(id)? = Some(table);
+            segment = &segment[1 + size..];
+        }
+        Some(())
+    }
+
+    /// Decodes the scan whose header is `header` and whose data starts at
+    /// `at`, and returns where the data ends.
+    fn read_scan(&mut self, header: &[u8], bytes: &[u8], at: usize) -> Option<usize> {
+        let frame = self.frame.as_mut()?;
+        let count = usize::from(*header.first()?);
+        let mut members = Vec::with_capacity(count);
+        for fields in header.get(1..1 + 2 * count)?.chunks_exact(2) {
+            let index = frame.components.iter().position(|c| c.id == fields[0])?;
+            let component = &mut frame.components[index];
+            component.dc_table = usize::from(fields[1] >> 4);
+            component.ac_table = usize::from(fields[1] & 15);
+            component.dc_prediction = 0;
+            members.push(index);
+        }
+        let fields = header.get(1 + 2 * count..4 + 2 * count)?;
+        let pass = if frame.progressive {
+            Pass {
+                progressive: true,
+                start: usize::from(fields[0]),
+                end: usize::from(fields[1]),
+                high: fields[2] >> 4,
+                low: fields[2] & 15,
+            }
+        } else {
+            Pass {
+                progressive: false,
+                start: 0,
+                end: 63,
+                high: 0,
+                low: 0,
+            }
+        };
+        // Progressive scans send DC coefficients apart from AC ones, and
+        // AC ones a component at a time.
+        let dc_only = pass.start == 0 && pass.end == 0;
+        if pass.end > 63
+            || pass.start > pass.end
+            || pass.low > 13
+            || members.is_empty()
+            || (pass.progressive && !dc_only && (pass.start == 0 || members.len() > 1))
+        {
+            return None;
+        }
+        let end = entropy_end(bytes, at);
+        let mut bits = Bits {
+            data: &bytes[at..end],
+            at: 0,
+            buffer: 0,
+            count: 0,
+        };
+        let mut eob_run = 0;
+        let mut mcu = 0;
+        let mut restart = |bits: &mut Bits, components: &mut [Component], eob_run: &mut u32| {
+            if self.restart_interval > 0 && mcu > 0 && mcu % self.restart_interval == 0 {
+                bits.restart();
+                components.iter_mut().for_each(|c| c.dc_prediction = 0);
+                *eob_run = 0;
+            }
+            mcu += 1;
+        };
+        let tables = (&self.dc_tables, &self.ac_tables);
+        if let [index] = members[..] {
+            // Alone in a scan, a component's blocks are its MCUs, and only
+            // those covering the image are sent.
+            let component = &frame.components[index];
+            let wide = (frame.width * component.horizontal)
+                .div_ceil(frame.max_horizontal)
+                .div_ceil(8);
+            let high = (frame.height * component.vertical)
+                .div_ceil(frame.max_vertical)
+                .div_ceil(8);
+            for row in 0..high {
+                for column in 0..wide {
+                    restart(&mut bits, &mut frame.components, &mut eob_run);
+                    let component = &mut frame.components[index];
+                    decode_block(
+                        component,
+                        row,
+                        column,
+                        tables,
+                        &mut bits,
+                        &mut eob_run,
+                        pass,
+                    )?;
+                }
+            }
+        } else {
+            for mcu_row in 0..frame.mcus_high {
+                for mcu_column in 0..frame.mcus_wide {
+                    restart(&mut bits, &mut frame.components, &mut eob_run);
+                    for &index in &members {
+                        let component = &mut frame.components[index];
+                        for v in 0..component.vertical {
+                            for h in 0..component.horizontal {
+                                let row = mcu_row * component.vertical + v;
+                                let column = mcu_column * component.horizontal + h;
+                                decode_block(
+                                    component,
+                                    row,
+                                    column,
+                                    tables,
+                                    &mut bits,
+                                    &mut eob_run,
+                                    pass,
+                                )?;
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        self.scans += 1;
+        Some(end)
+    }
+
+    /// Turns the coefficients into pixels: dequantized, transformed,
+    /// upsampled where a component is subsampled, and converted to RGB.
+    fn image(self) -> Option<Image> {
+        let frame = self.frame?;
+        if self.scans == 0 {
+            return None;
+        }
+        let cosines = idct_cosines();
+        let mut planes = Vec::with_capacity(frame.components.len());
+        for component in &frame.components {
+            let table = self.quantization[component.quantization].as_ref()?;
+            let stride = component.blocks_wide * 8;
+            let mut plane = vec![0u8; stride * component.blocks_high * 8];
+            for (i, block) in component.coefficients.chunks_exact(64).enumerate() {
+                let (row, column) = (i / component.blocks_wide, i % component.blocks_wide);
+                let samples = idct(block, table, &cosines);
+                for y in 0..8 {
+                    let start = (row * 8 + y) * stride + column * 8;
+                    plane[start..start + 8].copy_from_slice(&samples[y * 8..y * 8 + 8]);
+                }
+            }
+            planes.push((plane, stride));
+        }
+        let ids: Vec<u8> = frame.components.iter().map(|c| c.id).collect();
+        let rgb = self.adobe_transform == Some(0) || ids == b"RGB";
+        let mut pixels = Vec::with_capacity(frame.width * frame.height);
+        for y in 0..frame.height {
+            for x in 0..frame.width {
+                let sample = |c: usize| {
+                    let component = &frame.components[c];
+                    let (plane, stride) = &planes[c];
+                    let sx = x * component.horizontal / frame.max_horizontal;
+                    let sy = y * component.vertical / frame.max_vertical;
+                    plane[sy * stride + sx]
+                };
+                pixels.push(match planes.len() {
+                    1 => [sample(0), sample(0), sample(0), 255],
+                    _ if rgb => [sample(0), sample(1), sample(2), 255],
+                    _ => ycbcr_to_rgb(sample(0), sample(1), sample(2)),
+                });
+            }
+        }
+        Some(Image {
+            format: "JPEG",
+            width: frame.width,
+            height: frame.height,
+            pixels,
+        })
+    }
+}
+
+/// Decodes what `pass` carries of one block of `component`.
+fn decode_block(
+    component: &mut Component,
+    row: usize,
+    column: usize,
+    (dc_tables, ac_tables): (&[Option<Huffman>; 4], &[Option<Huffman>; 4]),
+    bits: &mut Bits,
+    eob_run: &mut u32,
+    pass: Pass,
+) -> Option<()> {
+    let start = (row * component.blocks_wide + column) * 64;
+    let block = component.coefficients.get_mut(start..start + 64)?;
+    let dc = dc_tables.get(component.dc_table)?.as_ref();
+    let ac = ac_tables.get(component.ac_table)?.as_ref();
+    let prediction = &mut component.dc_prediction;
+    if !pass.progressive {
+        decode_dc_first(block, prediction, dc?, bits, 0)?;
+        return decode_ac_first(block, ac?, bits, eob_run, 1, 63, 0);
+    }
+    match (pass.start, pass.high) {
+        (0, 0) => decode_dc_first(block, prediction, dc?, bits, pass.low),
+        (0, _) => {
+            if bits.read(1) == 1 {
+                block[0] |= 1 << pass.low;
+            }
+            Some(())
+        }
+        (_, 0) => decode_ac_first(block, ac?, bits, eob_run, pass.start, pass.end, pass.low),
+        _ => decode_ac_refine(block, ac?, bits, eob_run, pass.start, pass.end, pass.low),
+    }
+}
+
+fn decode_dc_first(
+    block: &mut [i16],
+    prediction: &mut i32,
+    table: &Huffman,
+    bits: &mut Bits,
+    low: u8,
+) -> Option<()> {
+    let size = table.decode(bits)?;
+    *prediction = prediction.wrapping_add(bits.receive(u32::from(size)));
+    block[0] = (*prediction << low) as i16;
+    Some(())
+}
+
+/// The first bits of coefficients `start` to `end`, or all of them in a
+/// sequential file. A run of blocks with none left is counted in `eob_run`.
+fn decode_ac_first(
+    block: &mut [i16],
+    table: &Huffman,
+    bits: &mut Bits,
+    eob_run: &mut u32,
+    start: usize,
+    end: usize,
+    low: u8,
+) -> Option<()> {
+    if *eob_run > 0 {
+        *eob_run -= 1;
+        return Some(());
+    }
+    let mut k = start;
+    while k <= end {
+        let symbol = table.decode(bits)?;
+        let (run, size) = (u32::from(symbol >> 4), u32::from(symbol & 15));
+        if size == 0 {
+            if run < 15 {
+                *eob_run = (1 << run) - 1 + bits.read(run);
+                break;
+            }
+            k += 16;
+            continue;
+        }
+        k += run as usize;
+        *block.get_mut(*ZIGZAG.get(k)?)? = (bits.receive(size) << low) as i16;
+        k += 1;
+    }
+    Some(())
+}
+
+/// The next bit of coefficients `start` to `end`: one more for those
+/// already seen, and the first for those turning up now, which can only be
+/// 1 or -1 at this bit.
+fn decode_ac_refine(
+    block: &mut [i16],
+    table: &Huffman,
+    bits: &mut Bits,
+    eob_run: &mut u32,
+    start: usize,
+    end: usize,
+    low: u8,
+) -> Option<()> {
+    let bit = 1i16 << low;
+    let refine = |coefficient: &mut i16, bits: &mut Bits| {
+        if bits.read(1) == 1 && *coefficient & bit == 0 {
+            let step = if *coefficient >= 0 { bit } else { -bit };
+            *coefficient = coefficient.wrapping_add(step);
+        }
+    };
+    let mut k = start;
+    if *eob_run == 0 {
+        while k <= end {
+            let symbol = table.decode(bits)?;
+            let (mut run, size) = (symbol >> 4, symbol & 15);
+            let mut value = 0;
+            if size == 0 {
+                if run < 15 {
+                    *eob_run = (1 << run) + bits.read(u32::from(run));
+                    break;
+                }
+            } else {
+                value = if bits.read(1) == 1 { bit } else { -bit };
+            }
+            // Skip `run` coefficients still zero, refining the others on
+            // the way, and put the new one in the next zero.
+            while k <= end {
+                let coefficient = &mut block[ZIGZAG[k]];
+                if *coefficient != 0 {
+                    refine(coefficient, bits);
+                } else if run == 0 {
+                    break;
+                } else {
+                    run -= 1;
+                }
+                k += 1;
+            }
+            if value != 0 {
+                *block.get_mut(*ZIGZAG.get(k)?)? = value;
+            }
+            k += 1;
+        }
+    }
+    if *eob_run > 0 {
+        for &position in ZIGZAG.get(k..=end).unwrap_or_default() {
+            if block[position] != 0 {
+                refine(&mut block[position], bits);
+            }
+        }
+        *eob_run -= 1;
+    }
+    Some(())
+}
+
+/// `cos((2x + 1)uπ/16)` for each sample `x` and frequency `u`, scaled for
+/// the inverse DCT of one dimension.
+fn idct_cosines() -> [[f32; 8]; 8] {
+    let mut cosines = [[0.0; 8]; 8];
+    for (x, row) in cosines.iter_mut().enumerate() {
+        for (u, cosine) in row.iter_mut().enumerate() {
+            let scale = if u == 0 { 0.5_f32.sqrt() } else { 1.0 };
+            let angle = (2 * x + 1) as f32 * u as f32 * std::f32::consts::PI / 16.0;
+            *cosine = scale * angle.cos() / 2.0;
+        }
+    }
+    cosines
+}
+
+/// The samples of one block from its coefficients, dequantized by `table`.
+fn idct(block: &[i16], table: &[u16; 64], cosines: &[[f32; 8]; 8]) -> [u8; 64] {
+    let level = |value: f32| (value + 128.0).round().clamp(0.0, 255.0) as u8;
+    // Flat blocks are common and need no transform.
+    if block[1..].iter().all(|&c| c == 0) {
+        return [level(f32::from(block[0]) * f32::from(table[0]) / 8.0); 64];
+    }
+    let coefficient = |i: usize| f32::from(block[i]) * f32::from(table[i]);
+    let mut rows = [0.0f32; 64];
+    for v in 0..8 {
+        for x in 0..8 {
+            rows[v * 8 + x] = (0..8).map(|u| cosines[x][u] * coefficient(v * 8 + u)).sum();
+        }
+    }
+    let mut samples = [0; 64];
+    for y in 0..8 {
+        for x in 0..8 {
+            samples[y * 8 + x] = level((0..8).map(|v| cosines[y][v] * rows[v * 8 + x]).sum());
+        }
+    }
+    samples
+}
+
+fn ycbcr_to_rgb(y: u8, cb: u8, cr: u8) -> [u8; 4] {
+    let (y, cb, cr) = (f32::from(y), f32::from(cb) - 128.0, f32::from(cr) - 128.0);
+    let channel = |value: f32| value.round().clamp(0.0, 255.0) as u8;
+    [
+        channel(y + 1.402 * cr),
+        channel(y - 0.344_136 * cb - 0.714_136 * cr),
+        channel(y + 1.772 * cb),
+        255,
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The symbols of the test tables, each coded as its index in 4 bits.
+    const DC_SYMBOLS: [u8; 12] = [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11];
+    const AC_SYMBOLS: [u8; 12] = [
+        0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x14, 0x15, 0xF0,
+    ];
+
+    /// Entropy-coded data as a test writes it, bit by bit.
+    #[derive(Default)]
+    struct Writer {
+        bytes: Vec<u8>,
+        buffer: u32,
+        count: u32,
+    }
+
+    impl Writer {
+        fn put(&mut self, value: u32, n: u32) {
+            for i in (0..n).rev() {
+                self.buffer = (self.buffer << 1) | ((value >> i) & 1);
+                self.count += 1;
+                if self.count == 8 {
+                    self.bytes.push(self.buffer as u8);
+                    if self.buffer == 0xFF {
+                        self.bytes.push(0);
+                    }
+                    (self.buffer, self.count) = (0, 0);
+                }
+            }
+        }
+
+        /// A DC coefficient's difference from the one before.
+        fn dc(&mut self, value: i32) {
+            let size = magnitude(value);
+            let symbol = DC_SYMBOLS
+                .iter()
+                .position(|&s| u32::from(s) == size)
+                .unwrap();
+            self.put(symbol as u32, 4);
+            self.put_bits(value, size);
+        }
+
+        /// An AC coefficient after `run` zeros, or with 0, the end of the
+        /// block.
+        fn ac(&mut self, run: u8, value: i32) {
+            let size = magnitude(value);
+            let symbol = (run << 4) | size as u8;
+            let code = AC_SYMBOLS.iter().position(|&s| s == symbol).unwrap();
+            self.put(code as u32, 4);
+            self.put_bits(value, size);
+        }
+
+        /// `value` in `size` bits, negative ones less one.
+        fn put_bits(&mut self, value: i32, size: u32) {
+            let bits = if value < 0 {
+                value + (1 << size) - 1
+            } else {
+                value
+            };
+            self.put(bits as u32, size);
+        }
+
+        /// The bytes written, the last one padded with ones.
+        fn finish(mut self) -> Vec<u8> {
+            if self.count > 0 {
+                self.put(0xFF, 8 - self.count);
+            }
+            self.bytes
+        }
+    }
+
+    /// The bits a coefficient takes, which JPEG calls its size.
+    fn magnitude(value: i32) -> u32 {
+        32 - value.unsigned_abs().leading_zeros()
+    }
+
+    /// A scan's components, band of coefficients, bit positions and data.
+    type Scan<'a> = (&'a [u8], (u8, u8), u8, Vec<u8>);
+
+    fn segment(bytes: &mut Vec<u8>, marker: u8, body: &[u8]) {
+        bytes.extend([0xFF, marker]);
+        bytes.extend(((body.len() + 2) as u16).to_be_bytes());
+        bytes.extend(body);
+    }
+
+    /// A JPEG with a frame of `components` (id and sampling factors), all
+    /// quantized by 1, and `scans` (components, band, bit positions, data).
+    fn jpeg(
+        sof: u8,
+        (width, height): (u16, u16),
+        components: &[(u8, u8)],
+        restart_interval: u16,
+        scans: &[Scan],
+    ) -> Vec<u8> {
+        let mut bytes = vec![0xFF, 0xD8];
+        segment(&mut bytes, 0xDB, &[[0].as_slice(), &[1; 64]].concat());
+        let mut frame = vec![8];
+        frame.extend(height.to_be_bytes());
+        frame.extend(width.to_be_bytes());
+        frame.push(components.len() as u8);
+        for &(id, sampling) in components {
+            frame.extend([id, sampling, 0]);
+        }
+        segment(&mut bytes, sof, &frame);
+        for (kind, symbols) in [(0x00, &DC_SYMBOLS), (0x10, &AC_SYMBOLS)] {
+            let mut counts = [0; 16];
+            counts[3] = symbols.len() as u8;
+            segment(&mut bytes, 0xC4, &[&[kind], &counts[..], symbols].concat());
+        }
+        if restart_interval > 0 {
+            segment(&mut bytes, 0xDD, &restart_interval.to_be_bytes());
+        }
+        for (ids, (start, end), bit_positions, data) in scans {
+            let mut header = vec![ids.len() as u8];
+            for &id in *ids {
+                header.extend([id, 0x00]);
+            }
+            header.extend([*start, *end, *bit_positions]);
+            segment(&mut bytes, 0xDA, &header);
+            bytes.extend(data);
+        }
+        bytes.extend([0xFF, 0xD9]);
+        bytes
+    }
+
+    fn grays(image: &Image) -> Vec<u8> {
+        image.pixels.iter().map(|p| p[0]).collect()
+    }
+
+    #[test]
+    fn baseline_blocks_and_dc_prediction() {
+        // A flat block shows its DC coefficient over 8, around 128.
+        let mut data = Writer::default();
+        data.dc(80);
+        data.ac(0, 0);
+        data.dc(-160);
+        data.ac(0, 0);
+        let bytes = jpeg(
+            0xC0,
+            (16, 8),
+            &[(1, 0x11)],
+            0,
+            &[(&[1], (0, 63), 0, data.finish())],
+        );
+        let image = decode(&bytes).unwrap();
+        assert_eq!((image.width, image.height), (16, 8));
+        let row: Vec<u8> = grays(&image)[..16].to_vec();
+        assert_eq!(row, [[138; 8], [118; 8]].concat());
+    }
+
+    #[test]
+    fn baseline_restart_resets_prediction() {
+        let mut first = Writer::default();
+        first.dc(80);
+        first.ac(0, 0);
+        let mut second = Writer::default();
+        second.dc(80);
+        second.ac(0, 0);
+        let data = [first.finish(), vec![0xFF, 0xD0], second.finish()].concat();
+        let bytes = jpeg(0xC0, (16, 8), &[(1, 0x11)], 1, &[(&[1], (0, 63), 0, data)]);
+        assert_eq!(grays(&decode(&bytes).unwrap())[..16], [138; 16]);
+    }
+
+    #[test]
+    fn subsampled_color() {
+        // One 4:2:0 MCU: four luma blocks, then one each of Cb and Cr.
+        let mut data = Writer::default();
+        for luma in [0, 80, -80, 80] {
+            data.dc(luma);
+            data.ac(0, 0);
+        }
+        data.dc(0);
+        data.ac(0, 0);
+        data.dc(8 * 40);
+        data.ac(0, 0);
+        let components = [(1, 0x22), (2, 0x11), (3, 0x11)];
+        let scan = (&[1, 2, 3][..], (0, 63), 0, data.finish());
+        let image = decode(&jpeg(0xC0, (16, 16), &components, 0, &[scan])).unwrap();
+        // Cr of 40 over 128 adds 1.402 times that to red.
+        assert_eq!(image.pixels[0], [184, 99, 128, 255]);
+        assert_eq!(image.pixels[15 * 16 + 8], [194, 109, 138, 255]);
+    }
+
+    #[test]
+    fn progressive_matches_baseline() {
+        // DC 81 and AC coefficients 25 and -25, sent whole...
+        let mut data = Writer::default();
+        data.dc(81);
+        data.ac(0, 25);
+        data.ac(1, -25);
+        data.ac(0, 0);
+        let baseline = jpeg(
+            0xC0,
+            (8, 8),
+            &[(1, 0x11)],
+            0,
+            &[(&[1], (0, 63), 0, data.finish())],
+        );
+        // ...and a bit short, with the last bits in refining scans.
+        let mut dc = Writer::default();
+        dc.dc(40);
+        let mut ac = Writer::default();
+        ac.ac(0, 12);
+        ac.ac(1, -12);
+        ac.ac(0, 0);
+        let mut dc_refine = Writer::default();
+        dc_refine.put(1, 1);
+        let mut ac_refine = Writer::default();
+        ac_refine.ac(0, 0);
+        ac_refine.put(0b11, 2);
+        let scans = [
+            (&[1][..], (0, 0), 0x01, dc.finish()),
+            (&[1][..], (1, 63), 0x01, ac.finish()),
+            (&[1][..], (0, 0), 0x10, dc_refine.finish()),
+            (&[1][..], (1, 63), 0x10, ac_refine.finish()),
+        ];
+        let progressive = jpeg(0xC2, (8, 8), &[(1, 0x11)], 0, &scans);
+        let (baseline, progressive) = (decode(&baseline).unwrap(), decode(&progressive).unwrap());
+        assert_eq!(baseline.pixels, progressive.pixels);
+        // The AC coefficients did shade the block.
+        assert_ne!(baseline.pixels[0], baseline.pixels[63]);
+    }
+
+    #[test]
+    fn rejects_what_it_cannot_decode() {
+        let mut data = Writer::default();
+        data.dc(0);
+        data.ac(0, 0);
+        let scan = || (&[1][..], (0, 63), 0, data.bytes.clone());
+        // Lossless, and arithmetic coded.
+        assert!(decode(&jpeg(0xC3, (8, 8), &[(1, 0x11)], 0, &[scan()])).is_none());
+        assert!(decode(&jpeg(0xC9, (8, 8), &[(1, 0x11)], 0, &[scan()])).is_none());
+        // Four components.
+        let cmyk = [(1, 0x11), (2, 0x11), (3, 0x11), (4, 0x11)];
+        assert!(decode(&jpeg(0xC0, (8, 8), &cmyk, 0, &[scan()])).is_none());
+        // No scans at all.
+        assert!(decode(&jpeg(0xC0, (8, 8), &[(1, 0x11)], 0, &[])).is_none());
+    }
+}
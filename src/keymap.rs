@@ -0,0 +1,260 @@
+//! The single-key commands of the listing and the keys they are bound to,
+//! which `[keys]` in the config file can change.
+
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Action {
+    Quit,
+    Down,
+    Up,
+    Open,
+    Parent,
+    Bottom,
+    NextLetter,
+    PreviousLetter,
+    Recent,
+    Filter,
+    Hidden,
+    GitIgnored,
+    Sort,
+    ReverseSort,
+    LoadAll,
+    /// Scrolls the preview, or goes to the next match while searching it.
+    PreviewDown,
+    PreviewUp,
+    NextPage,
+    PreviousPage,
+    SearchPreview,
+    PreviousMatch,
+    ForcePreview,
+    Wrap,
+    ScrollLeft,
+    ScrollRight,
+    Theme,
+    CopyPath,
+    CopyName,
+    CopyContents,
+    Emit,
+    Checksum,
+    AllocatedSize,
+    ReadOnly,
+    HardLinks,
+    Mark,
+    Delete,
+    Rename,
+    Cut,
+    Copy,
+    Paste,
+    NewFile,
+    NewDirectory,
+    MoveToQuickmark,
+    CopyToQuickmark,
+    NewFromTemplate,
+    Shell,
+    Nested,
+    Help,
+}
+
+/// Every action with its name in the config file, default key and the
+/// description `?` shows, in the order `?` lists them.
+const ACTIONS: &[(Action, &str, char, &str)] = &[
+    (Action::Down, "down", 'j', "move down"),
+    (Action::Up, "up", 'k', "move up"),
+    (Action::Bottom, "bottom", 'G', "last entry"),
+    (Action::NextLetter, "next_letter", '}', "next first letter"),
+    (
+        Action::PreviousLetter,
+        "previous_letter",
+        '{',
+        "previous first letter",
+    ),
+    (
+        Action::Open,
+        "open",
+        'l',
+        "open directory, or scroll the preview right",
+    ),
+    (
+        Action::Parent,
+        "parent",
+        'h',
+        "parent directory, or scroll the preview left",
+    ),
+    (Action::Recent, "recent", '-', "cycle recent directories"),
+    (Action::Filter, "filter", '/', "filter the listing"),
+    (Action::Hidden, "hidden", '.', "show hidden files"),
+    (
+        Action::GitIgnored,
+        "git_ignored",
+        'I',
+        "hide git-ignored files",
+    ),
+    (Action::Sort, "sort", 's', "cycle sort order"),
+    (
+        Action::ReverseSort,
+        "reverse_sort",
+        'S',
+        "reverse sort order",
+    ),
+    (
+        Action::LoadAll,
+        "load_all",
+        'M',
+        "load a capped directory fully",
+    ),
+    (
+        Action::PreviewDown,
+        "preview_down",
+        'n',
+        "scroll preview down / next match",
+    ),
+    (Action::PreviewUp, "preview_up", 'u', "scroll preview up"),
+    (Action::NextPage, "next_page", ']', "next preview page"),
+    (
+        Action::PreviousPage,
+        "previous_page",
+        '[',
+        "previous preview page",
+    ),
+    (
+        Action::SearchPreview,
+        "search_preview",
+        '\\',
+        "search preview",
+    ),
+    (
+        Action::PreviousMatch,
+        "previous_match",
+        'N',
+        "previous match",
+    ),
+    (
+        Action::ForcePreview,
+        "force_preview",
+        'F',
+        "preview a generated file",
+    ),
+    (Action::Wrap, "wrap", 'w', "wrap long preview lines"),
+    (
+        Action::ScrollLeft,
+        "scroll_left",
+        '<',
+        "scroll preview left",
+    ),
+    (
+        Action::ScrollRight,
+        "scroll_right",
+        '>',
+        "scroll preview right",
+    ),
+    (Action::Theme, "theme", 't', "cycle theme"),
+    (Action::CopyPath, "copy_path", 'y', "copy path"),
+    (Action::CopyName, "copy_name", 'Y', "copy name"),
+    (
+        Action::CopyContents,
+        "copy_contents",
+        'C',
+        "copy file contents",
+    ),
+    (Action::Emit, "emit", 'e', "send path to [emit]"),
+    (Action::Checksum, "checksum", '#', "checksum"),
+    (
+        Action::AllocatedSize,
+        "allocated_size",
+        'i',
+        "allocated size",
+    ),
+    (Action::ReadOnly, "read_only", 'W', "toggle read-only"),
+    (Action::HardLinks, "hard_links", 'L', "other hard links"),
+    (Action::Mark, "mark", ' ', "mark / unmark"),
+    (Action::Delete, "delete", 'd', "delete marked, or selected"),
+    (Action::Rename, "rename", 'r', "rename"),
+    (Action::Cut, "cut", 'x', "cut marked, or selected"),
+    (Action::Copy, "copy", 'c', "copy marked, or selected"),
+    (Action::Paste, "paste", 'p', "paste here"),
+    (
+        Action::NewFile,
+        "new_file",
+        'a',
+        "new file (name/ for a directory)",
+    ),
+    (Action::NewDirectory, "new_directory", 'A', "new directory"),
+    (
+        Action::MoveToQuickmark,
+        "move_to_quickmark",
+        'b',
+        "move to a quickmark",
+    ),
+    (
+        Action::CopyToQuickmark,
+        "copy_to_quickmark",
+        'B',
+        "copy to a quickmark",
+    ),
+    (
+        Action::NewFromTemplate,
+        "new_from_template",
+        'T',
+        "new file from template",
+    ),
+    (Action::Shell, "shell", '!', "shell in directory"),
+    (Action::Nested, "nested", 'O', "nested lazycat"),
+    (Action::Quit, "quit", 'q', "quit"),
+    (Action::Help, "help", '?', "this help"),
+];
+
+impl Action {
+    pub fn from_name(name: &str) -> Option<Self> {
+        ACTIONS
+            .iter()
+            .find(|(_, n, _, _)| *n == name)
+            .map(|(action, ..)| *action)
+    }
+}
+
+pub type Keymap = HashMap<char, Action>;
+
+/// The first keys of the two-key commands, which aren't in the keymap and
+/// so can't be bound to an action either.
+pub const PREFIX_KEYS: &[char] = &['z', 'm', '\'', '`', 'g'];
+
+pub fn default_keymap() -> Keymap {
+    ACTIONS
+        .iter()
+        .map(|(action, _, key, _)| (*key, *action))
+        .collect()
+}
+
+/// Binds `action` to `key` instead of whatever keys it had, taking `key`
+/// away from any other action.
+pub fn rebind(keymap: &mut Keymap, action: Action, key: char) {
+    keymap.retain(|_, bound| *bound != action);
+    keymap.insert(key, action);
+}
+
+/// The `?` listing: the keys each action is bound to, and what it does.
+pub fn help(keymap: &Keymap) -> Vec<(String, &'static str)> {
+    ACTIONS
+        .iter()
+        .filter_map(|(action, _, _, description)| {
+            let mut keys: Vec<char> = keymap
+                .iter()
+                .filter(|(_, bound)| *bound == action)
+                .map(|(key, _)| *key)
+                .collect();
+            if keys.is_empty() {
+                return None;
+            }
+            keys.sort();
+            let keys: Vec<String> = keys
+                .into_iter()
+                .map(|key| match key {
+                    ' ' => "Space".to_string(),
+                    key => key.to_string(),
+                })
+                .collect();
+            Some((keys.join(" "), *description))
+        })
+        .collect()
+}
@@ -0,0 +1,40 @@
+//! A small least-recently-used cache, for the handful of previews worth
+//! keeping around.
+
+use std::collections::VecDeque;
+
+pub struct Lru<K, V> {
+    /// Most recently used last.
+    entries: VecDeque<(K, V)>,
+    capacity: usize,
+}
+
+impl<K: PartialEq, V> Lru<K, V> {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            entries: VecDeque::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    /// Looks up `key`, marking it as just used.
+    pub fn get(&mut self, key: &K) -> Option<&V> {
+        let index = self.entries.iter().position(|(k, _)| k == key)?;
+        let entry = self.entries.remove(index)?;
+        self.entries.push_back(entry);
+        self.entries.back().map(|(_, v)| v)
+    }
+
+    /// Stores `value`, evicting the least recently used entry if full.
+    pub fn insert(&mut self, key: K, value: V) {
+        self.entries.retain(|(k, _)| *k != key);
+        if self.entries.len() == self.capacity {
+            self.entries.pop_front();
+        }
+        self.entries.push_back((key, value));
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+}
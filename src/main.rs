@@ -1,21 +1,69 @@
+mod ansi;
+mod checksum;
+mod clipboard;
+mod config;
+mod diff;
+mod emit;
+mod encrypted;
+mod fileops;
+mod format;
+mod fuzzy;
+mod git;
+mod glob;
+mod hardlinks;
+mod hexdump;
+mod icons;
+mod image;
+mod keymap;
+mod lru;
+mod markup;
+mod search;
+mod split;
+mod state;
+mod statusline;
+mod templates;
+
+use config::{Config, CurrentLine, ExtensionDisplay, LineNumbers};
 use crossterm::{
-    event::{self, Event, KeyCode, KeyEventKind},
+    event::{
+        self, DisableMouseCapture, EnableMouseCapture, Event, KeyCode, KeyEvent, KeyEventKind,
+        KeyModifiers, MouseButton, MouseEvent, MouseEventKind,
+    },
     terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
     ExecutableCommand,
 };
+use format::human_size;
+use keymap::{Action, Keymap};
 use ratatui::{
-    layout::{Constraint, Direction, Layout},
+    layout::{Constraint, Direction, Layout, Margin, Rect},
     prelude::CrosstermBackend,
     style::{Color, Modifier, Style},
-    text::{Line, Span},
-    widgets::{Block, Borders, List, ListItem, ListState, Paragraph},
-    Terminal,
+    text::{Line, Span, Text},
+    widgets::{
+        Block, Borders, Clear, List, ListItem, ListState, Paragraph, Scrollbar,
+        ScrollbarOrientation, ScrollbarState, Wrap,
+    },
+    Frame, Terminal,
 };
+use state::State;
+use statusline::Field;
 use std::{
+    cell::RefCell,
+    collections::{HashMap, HashSet, VecDeque},
     env,
-    fs::{self, DirEntry},
-    io::{self, stdout},
-    path::PathBuf,
+    ffi::{OsStr, OsString},
+    fs::{self, DirEntry, File},
+    io::{self, stdout, BufRead, BufReader, Read, Seek, SeekFrom},
+    ops::Range,
+    path::{Path, PathBuf},
+    process::{Command, ExitStatus, Stdio},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        mpsc::{self, Receiver, Sender},
+        Arc,
+    },
+    thread,
+    time::{Duration, Instant, SystemTime},
 };
 use syntect::{
     easy::HighlightLines,
@@ -23,284 +71,4412 @@ use syntect::{
     parsing::SyntaxSet,
     util::LinesWithEndings,
 };
+use unicode_width::UnicodeWidthChar;
+
+/// Extra rows built past the bottom of the list pane, so a frame never comes
+/// up short while the offset catches up with the selection.
+const LIST_RENDER_BUFFER: usize = 8;
+
+/// Files bigger than this are previewed a page at a time, reading only the
+/// requested page from disk instead of the whole file.
+const PREVIEW_PAGE_BYTES: u64 = 64 * 1024;
+
+/// Which page of a paged file preview is on screen.
+struct PreviewPage {
+    page: u64,
+    page_count: u64,
+    /// File line number of the page's first line, tracked as pages are
+    /// stepped through so that no earlier page has to be reread.
+    first_line: usize,
+    /// How many lines the page holds.
+    line_count: usize,
+}
+
+/// Byte offset where `page` begins: the first line start at or after
+/// `page * PREVIEW_PAGE_BYTES`, so a page never opens halfway through a line.
+fn page_start(reader: &mut BufReader<File>, page: u64, len: u64) -> io::Result<u64> {
+    let target = page * PREVIEW_PAGE_BYTES;
+    if target == 0 || target >= len {
+        return Ok(target.min(len));
+    }
+    // Start one byte early so a line that begins exactly at `target` is kept.
+    reader.seek(SeekFrom::Start(target - 1))?;
+    let skipped = reader.skip_until(b'\n')? as u64;
+    Ok((target - 1 + skipped).min(len))
+}
+
+/// Reads the bytes of one page. The result is empty when a single line
+/// spans the whole page, because no line starts inside it.
+fn read_page(path: &PathBuf, page: u64) -> io::Result<Vec<u8>> {
+    let file = File::open(path)?;
+    let len = file.metadata()?.len();
+    let mut reader = BufReader::new(file);
+    let start = page_start(&mut reader, page, len)?;
+    let end = page_start(&mut reader, page + 1, len)?;
+    // Overlong lines can push `end` far past the page; cap what is held.
+    let capped = end > start + 2 * PREVIEW_PAGE_BYTES;
+    let end = end.min(start + 2 * PREVIEW_PAGE_BYTES);
+    reader.seek(SeekFrom::Start(start))?;
+    let mut buf = Vec::with_capacity((end - start) as usize);
+    reader.take(end - start).read_to_end(&mut buf)?;
+    if capped {
+        buf.truncate(complete_utf8_len(&buf));
+    }
+    Ok(buf)
+}
+
+/// Length of `bytes` minus any multi-byte UTF-8 character cut off at the
+/// end, so a read that stops mid-character doesn't decode to a stray
+/// replacement glyph.
+fn complete_utf8_len(bytes: &[u8]) -> usize {
+    // A character is at most four bytes, so only the last three can be the
+    // start of one that didn't fit.
+    for back in 1..=bytes.len().min(3) {
+        let byte = bytes[bytes.len() - back];
+        if byte & 0xc0 == 0x80 {
+            continue;
+        }
+        let width = match byte {
+            0xc0..=0xdf => 2,
+            0xe0..=0xef => 3,
+            0xf0..=0xf7 => 4,
+            _ => 1,
+        };
+        return if width > back {
+            bytes.len() - back
+        } else {
+            bytes.len()
+        };
+    }
+    bytes.len()
+}
+
+const DEFAULT_THEME: &str = "base16-ocean.dark";
+
+/// How much of a file is searched for a NUL byte to decide it is binary.
+const BINARY_SNIFF_BYTES: usize = 8 * 1024;
+
+/// How much of a binary file the hex dump shows. The same read serves as
+/// the whole of a file too small to page, so it can't be any less.
+const HEX_DUMP_BYTES: usize = 64 * 1024;
+const _: () = assert!(HEX_DUMP_BYTES as u64 >= PREVIEW_PAGE_BYTES);
+
+/// Lines highlighted per message from the highlighting thread, so the top
+/// of a file is coloured long before the rest of it is done.
+const HIGHLIGHT_CHUNK_LINES: usize = 200;
+
+/// A run of highlighted lines from the background highlighter, taking the
+/// place of the plain lines starting at `start`.
+struct HighlightChunk {
+    generation: u64,
+    start: usize,
+    lines: Vec<Line<'static>>,
+    done: bool,
+}
+
+/// An image drawn by the background decoder, with the image itself kept
+/// for drawing again at another size. `None` if it couldn't be decoded.
+struct ImagePreview {
+    generation: u64,
+    image: Option<Arc<image::Image>>,
+    lines: Vec<Line<'static>>,
+}
+
+/// How many finished file previews are kept for going back to.
+const PREVIEW_CACHE_SIZE: usize = 16;
+
+/// A finished file preview, as `update_preview` left it.
+struct CachedPreview {
+    lines: Vec<Line<'static>>,
+    first_line: Option<usize>,
+    truncated: bool,
+}
+
+/// Entries read before a directory is first drawn; any more are read by
+/// a background thread and merged in as they arrive.
+const LISTING_FIRST_CHUNK: usize = 1000;
+
+/// Entries per message from the listing thread.
+const LISTING_CHUNK: usize = 5000;
+
+/// More of the directory being listed, from the listing thread.
+struct ListingChunk {
+    generation: u64,
+    entries: Vec<DirEntry>,
+    done: bool,
+}
+
+/// What git has to say about the directory being listed, from the thread
+/// `refresh_entries` starts, as git can take a while in a big repository.
+struct GitReport {
+    generation: u64,
+    statuses: Option<HashMap<OsString, git::Status>>,
+    /// Only looked for while ignored entries are hidden.
+    ignored: Option<HashSet<OsString>>,
+}
+
+/// Whether `entry` is a directory, or a link to one. Only links cost a
+/// stat; the rest is known from reading the directory.
+fn entry_is_dir(entry: &DirEntry) -> bool {
+    match entry.file_type() {
+        Ok(t) if t.is_symlink() => entry.path().is_dir(),
+        Ok(t) => t.is_dir(),
+        Err(_) => entry.path().is_dir(),
+    }
+}
+
+/// How many children of a directory are looked at when hunting for its most
+/// recently modified file, so huge directories don't stall the preview.
+const LATEST_FILE_SCAN_LIMIT: usize = 2000;
+
+/// Dotfiles, which are left out of listings unless hidden files are shown.
+fn is_hidden(name: &OsStr) -> bool {
+    name.as_encoded_bytes().starts_with(b".")
+}
+
+/// The most recently modified regular file directly inside `dir`, if any.
+fn latest_modified_file(dir: &PathBuf, show_hidden: bool) -> Option<PathBuf> {
+    fs::read_dir(dir)
+        .ok()?
+        .filter_map(|e| e.ok())
+        .filter(|e| show_hidden || !is_hidden(&e.file_name()))
+        .take(LATEST_FILE_SCAN_LIMIT)
+        .filter_map(|e| {
+            let metadata = e.metadata().ok()?;
+            let modified = metadata.modified().ok()?;
+            metadata.is_file().then(|| (modified, e.path()))
+        })
+        .max_by_key(|(modified, _)| *modified)
+        .map(|(_, path)| path)
+}
+
+/// How many entries the search for a file's other hard links looks at
+/// before giving up.
+const HARDLINK_SCAN_LIMIT: usize = 100_000;
+
+/// Largest file whose contents `C` copies whole; bigger files offer to copy
+/// just this much. OSC 52 payloads much larger than this are commonly
+/// dropped by terminals.
+const COPY_CONTENTS_LIMIT: usize = 256 * 1024;
+
+/// An action held back until the user answers `y` to `prompt`.
+struct Confirm {
+    prompt: String,
+    action: ConfirmAction,
+}
+
+enum ConfirmAction {
+    CopyText { text: String, what: String },
+    Delete(Vec<PathBuf>),
+    SetReadOnly { path: PathBuf, read_only: bool },
+}
+
+/// Entries cut or copied, waiting to be pasted.
+struct Register {
+    paths: Vec<PathBuf>,
+    cut: bool,
+}
+
+/// A paste under way, paused while its next entry collides with one
+/// already in the directory.
+struct Paste {
+    /// Entries still to paste; the first is the one in question.
+    queue: VecDeque<PathBuf>,
+    cut: bool,
+    pasted: usize,
+    skipped: usize,
+    failure: Option<String>,
+    /// The question shown while waiting on a `Collision`.
+    prompt: Option<String>,
+}
+
+/// What to do about a pasted entry whose name is taken.
+#[derive(Clone, Copy)]
+enum Collision {
+    Overwrite,
+    Skip,
+    Rename,
+}
+
+/// A line of text being typed into the status bar.
+struct Input {
+    prompt: String,
+    text: String,
+    /// Why the last submission was rejected; the prompt stays open.
+    error: Option<String>,
+    purpose: InputPurpose,
+}
+
+enum InputPurpose {
+    TemplateFileName {
+        template: PathBuf,
+    },
+    Rename {
+        from: PathBuf,
+    },
+    /// A relative path, whose missing parents are created too.
+    NewFile,
+    NewDirectory,
+    PreviewSearch,
+}
+
+/// A search through the preview's lines.
+struct PreviewSearch {
+    query: String,
+    matches: Vec<search::Match>,
+    /// Index into `matches` of the one last jumped to.
+    current: usize,
+}
+
+/// A popup list to choose one item from.
+struct Picker {
+    title: String,
+    items: Vec<String>,
+    selected: usize,
+    purpose: PickerPurpose,
+}
+
+enum PickerPurpose {
+    /// Quickmarked directories to move (or copy) `path` into.
+    FileAway {
+        path: PathBuf,
+        copy: bool,
+        dirs: Vec<PathBuf>,
+    },
+    /// Other paths to the same file; picking one jumps to it.
+    HardLinks(Vec<PathBuf>),
+    Template(Vec<PathBuf>),
+}
+
+/// How long `-` waits for another press before jumping to the chosen
+/// recent directory.
+const RECENT_CYCLE_COMMIT: Duration = Duration::from_millis(800);
+
+/// An in-progress cycle through the recent directories with `-`.
+struct RecentCycle {
+    /// Index into `State::recent_dirs` of the directory that would be
+    /// jumped to.
+    index: usize,
+    last_press: Instant,
+}
+
+/// How long the selection has to stay put after `j` / `k` before the
+/// preview catches up with it.
+const PREVIEW_DEBOUNCE: Duration = Duration::from_millis(80);
+
+/// How soon a second click on the same entry counts as a double click.
+const DOUBLE_CLICK: Duration = Duration::from_millis(400);
+
+/// How many rows a wheel notch moves the selection or the preview.
+const WHEEL_LINES: u16 = 3;
+
+/// How long the first key of a two-key command like `gg` waits for the
+/// second; after that the next key stands on its own.
+const PENDING_KEY_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// How one of the fixed commands is typed.
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Trigger {
+    Key(KeyCode),
+    Ctrl(char),
+    /// The second key of a two-key command; the first is one of
+    /// `keymap::PREFIX_KEYS`.
+    Prefixed(char, char),
+    /// Any key after the prefix, handed to the command.
+    PrefixedAny(char),
+}
+
+impl Trigger {
+    /// Whether `key` types this, with `prefix` typed just before it.
+    fn matches(self, prefix: Option<char>, key: &KeyEvent) -> bool {
+        match (self, prefix, key.code) {
+            (Trigger::Key(code), None, pressed) => pressed == code,
+            (Trigger::Ctrl(c), None, KeyCode::Char(pressed)) => {
+                pressed == c && key.modifiers.contains(KeyModifiers::CONTROL)
+            }
+            (Trigger::Prefixed(p, c), Some(typed), KeyCode::Char(pressed)) => {
+                typed == p && pressed == c
+            }
+            (Trigger::PrefixedAny(p), Some(typed), KeyCode::Char(_)) => typed == p,
+            _ => false,
+        }
+    }
+
+    fn label(self) -> String {
+        match self {
+            Trigger::Key(KeyCode::PageDown) => "PgDn".to_string(),
+            Trigger::Key(KeyCode::PageUp) => "PgUp".to_string(),
+            Trigger::Key(code) => format!("{:?}", code),
+            Trigger::Ctrl(c) => format!("Ctrl-{}", c),
+            Trigger::Prefixed(p, c) => format!("{}{}", p, c),
+            Trigger::PrefixedAny(p) => format!("{}<c>", p),
+        }
+    }
+}
+
+/// A fixed command, given the key typed after its prefix.
+type FixedCommand = fn(&mut App, char);
+
+/// The commands on keys outside `keymap`, which can't be rebound: how
+/// they're typed, what `?` says about them, and what they do.
+const FIXED_KEYS: &[(&[Trigger], &str, FixedCommand)] = &[
+    (&[Trigger::Key(KeyCode::Down)], "move down", |app, _| {
+        app.move_down()
+    }),
+    (&[Trigger::Key(KeyCode::Up)], "move up", |app, _| {
+        app.move_up()
+    }),
+    (
+        &[Trigger::Key(KeyCode::Right)],
+        "open directory, or scroll the preview right",
+        |app, _| app.step_right(),
+    ),
+    (
+        &[Trigger::Key(KeyCode::Enter)],
+        "open directory",
+        |app, _| app.enter_directory(),
+    ),
+    (
+        &[Trigger::Key(KeyCode::Left)],
+        "parent directory, or scroll the preview left",
+        |app, _| app.step_left(),
+    ),
+    (
+        &[Trigger::Key(KeyCode::Tab)],
+        "focus the preview / the listing",
+        |app, _| app.preview_focused = !app.preview_focused,
+    ),
+    (&[Trigger::Prefixed('g', 'g')], "first entry", |app, _| {
+        app.move_to_top()
+    }),
+    (
+        &[Trigger::Key(KeyCode::PageDown), Trigger::Ctrl('f')],
+        "page down",
+        |app, _| app.page_down(),
+    ),
+    (
+        &[Trigger::Key(KeyCode::PageUp), Trigger::Ctrl('b')],
+        "page up",
+        |app, _| app.page_up(),
+    ),
+    (
+        &[Trigger::Ctrl('d')],
+        "scroll preview down half a page",
+        |app, _| {
+            app.scroll_preview_down((app.preview_height / 2).max(1));
+        },
+    ),
+    (
+        &[Trigger::Ctrl('u')],
+        "scroll preview up half a page",
+        |app, _| {
+            app.scroll_preview_up((app.preview_height / 2).max(1));
+        },
+    ),
+    (&[Trigger::PrefixedAny('m')], "set quickmark", |app, c| {
+        if c.is_ascii_alphanumeric() {
+            app.set_quickmark(c);
+        }
+    }),
+    (
+        &[Trigger::PrefixedAny('\''), Trigger::PrefixedAny('`')],
+        "jump to quickmark",
+        |app, c| app.jump_to_quickmark(c),
+    ),
+    (
+        &[Trigger::Prefixed('z', 'd')],
+        "directories only",
+        |app, _| {
+            app.toggle_type_filter(Some(EntryKind::Directory));
+        },
+    ),
+    (&[Trigger::Prefixed('z', 'f')], "files only", |app, _| {
+        app.toggle_type_filter(Some(EntryKind::File));
+    }),
+    (&[Trigger::Prefixed('z', 's')], "swap panes", |app, _| {
+        app.swap_panes = !app.swap_panes;
+    }),
+    (
+        &[Trigger::Prefixed('z', 'z')],
+        "keep selection centered",
+        |app, _| {
+            app.center_selection = !app.center_selection;
+        },
+    ),
+    (
+        &[Trigger::Prefixed('z', '2')],
+        "two-line entries",
+        |app, _| {
+            app.toggle_two_line_entries();
+        },
+    ),
+    (
+        &[Trigger::Prefixed('z', 'i')],
+        "file type icons",
+        |app, _| app.toggle_icons(),
+    ),
+    (
+        &[Trigger::Prefixed('z', 'e')],
+        "cycle extension display",
+        |app, _| {
+            app.cycle_extension_display();
+        },
+    ),
+    (
+        &[Trigger::Prefixed('z', 'c')],
+        "directory entry counts",
+        |app, _| {
+            app.show_dir_counts = !app.show_dir_counts;
+        },
+    ),
+    (&[Trigger::Prefixed('z', 't')], "age heatmap", |app, _| {
+        app.heatmap = !app.heatmap;
+    }),
+    (
+        &[Trigger::Prefixed('z', 'l')],
+        "preview latest file in directory",
+        |app, _| {
+            app.toggle_preview_latest_in_dir();
+        },
+    ),
+    (&[Trigger::Prefixed('z', 'r')], "raw markup", |app, _| {
+        app.toggle_raw_markup()
+    }),
+    (&[Trigger::Prefixed('z', 'n')], "line numbers", |app, _| {
+        app.show_line_numbers = !app.show_line_numbers;
+    }),
+    (&[Trigger::Prefixed('z', '|')], "column guide", |app, _| {
+        app.show_guide = !app.show_guide;
+    }),
+    (
+        &[Trigger::Key(KeyCode::Esc)],
+        "clear search / filter / focus, or quit",
+        |app, _| app.escape(),
+    ),
+];
+
+/// The fixed command `key` types, with `prefix` typed just before it.
+fn fixed_command(prefix: Option<char>, key: &KeyEvent) -> Option<FixedCommand> {
+    FIXED_KEYS
+        .iter()
+        .find(|(triggers, ..)| triggers.iter().any(|t| t.matches(prefix, key)))
+        .map(|(.., command)| *command)
+}
+
+/// The `?` listing of `FIXED_KEYS`.
+fn fixed_key_help() -> Vec<(String, &'static str)> {
+    FIXED_KEYS
+        .iter()
+        .map(|(triggers, description, _)| {
+            let keys: Vec<String> = triggers.iter().map(|t| t.label()).collect();
+            (keys.join(" / "), *description)
+        })
+        .collect()
+}
+
+/// Drops the first `columns` display columns of `line`, for scrolling it
+/// sideways. A wide character cut in half leaves a space in its place, so
+/// the rest of the line stays in its columns.
+fn skip_columns(line: &mut Line<'static>, columns: usize) {
+    let mut left = columns;
+    for span in &mut line.spans {
+        if left == 0 {
+            break;
+        }
+        let mut chars = span.content.char_indices().peekable();
+        let mut pad = false;
+        while left > 0
+            && let Some((_, c)) = chars.next()
+        {
+            let width = c.width().unwrap_or(0);
+            pad = width > left;
+            left = left.saturating_sub(width);
+        }
+        // Marks combining with the last character skipped go with it.
+        while chars.next_if(|(_, c)| c.width() == Some(0)).is_some() {}
+        let rest = chars.peek().map_or("", |&(i, _)| &span.content[i..]);
+        span.content = format!("{}{}", if pad { " " } else { "" }, rest).into();
+    }
+    line.spans.retain(|span| !span.content.is_empty());
+}
+
+/// A rectangle of `width` x `height` cells centred in `area`, shrunk to fit.
+fn centered_rect(area: Rect, width: u16, height: u16) -> Rect {
+    let width = width.min(area.width);
+    let height = height.min(area.height);
+    Rect {
+        x: area.x + (area.width - width) / 2,
+        y: area.y + (area.height - height) / 2,
+        width,
+        height,
+    }
+}
+
+/// Restricts the listing to one kind of entry.
+#[derive(Clone, Copy, PartialEq)]
+enum EntryKind {
+    Directory,
+    File,
+}
+
+/// What the listing is ordered by. Directories always come first.
+#[derive(Clone, Copy, PartialEq)]
+enum SortMode {
+    Name,
+    Size,
+    ModifiedTime,
+}
+
+impl SortMode {
+    fn next(self) -> Self {
+        match self {
+            Self::Name => Self::Size,
+            Self::Size => Self::ModifiedTime,
+            Self::ModifiedTime => Self::Name,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::Name => "name",
+            Self::Size => "size",
+            Self::ModifiedTime => "modified time",
+        }
+    }
+}
 
 struct App {
     current_dir: PathBuf,
     entries: Vec<DirEntry>,
+    type_filter: Option<EntryKind>,
+    /// The `/` query narrowing the listing, if any.
+    filter: Option<String>,
+    /// Whether keys are going into the filter query.
+    filter_editing: bool,
+    /// Entries of the current directory the filter hides.
+    filtered_out: Vec<DirEntry>,
+    /// The size or modified time each entry is sorted by, so entries can be
+    /// merged back into the listing without being stat'ed again. Empty when
+    /// sorting by name.
+    sort_metrics: HashMap<OsString, u128>,
+    show_hidden: bool,
+    /// Whether entries the enclosing git repository ignores are left out.
+    hide_ignored: bool,
+    /// What `hide_ignored` leaves out of the current directory; `None` when
+    /// it is off or outside a repository.
+    git_ignored: Option<HashSet<OsString>>,
+    /// Entries picked out with Space for `d` to act on together, from any
+    /// directory.
+    marked: HashSet<PathBuf>,
+    sort_mode: SortMode,
+    sort_descending: bool,
     selected: usize,
+    list_offset: usize,
+    /// Entries the list pane fit when last drawn: a page for PageUp and
+    /// PageDown.
+    list_rows: usize,
+    /// Whether `visible_range` keeps the selection in the middle row.
+    center_selection: bool,
     preview_lines: Vec<Line<'static>>,
     preview_scroll: u16,
-    syntax_set: SyntaxSet,
+    /// Columns scrolled off the left of the preview while not wrapping.
+    preview_hscroll: u16,
+    /// Whether long preview lines wrap instead of running off the pane.
+    wrap_enabled: bool,
+    /// Whether Tab handed the sideways keys to the preview: the ones that
+    /// open a directory and go to the parent scroll it right and left.
+    preview_focused: bool,
+    preview_height: u16,
+    /// Where the panes were last drawn, for telling what a click hit.
+    list_area: Rect,
+    preview_area: Rect,
+    /// The entry last clicked and when, to spot a double click.
+    last_click: Option<(usize, Instant)>,
+    preview_page: Option<PreviewPage>,
+    /// File line number of the first of `preview_lines` when they are the
+    /// file's own lines, one per line, so they can be numbered.
+    preview_first_line: Option<usize>,
+    show_line_numbers: bool,
+    /// Set when the preview ends in a `-- truncated --` line.
+    preview_truncated: bool,
+    preview_byte_limit: usize,
+    /// The cells an image preview was drawn to fit, to redraw it when the
+    /// pane changes size.
+    preview_image_size: Option<(u16, u16)>,
+    /// The image shown, once decoded.
+    preview_image: Option<Arc<image::Image>>,
+    /// A file that turned out not to decode, to preview as bytes instead.
+    undecodable_image: Option<PathBuf>,
+    preview_search: Option<PreviewSearch>,
+    /// Finished previews by file and modification time, so an edited file
+    /// misses.
+    preview_cache: lru::Lru<(PathBuf, SystemTime), CachedPreview>,
+    /// The cache key of the preview on screen until it is finished and
+    /// cached.
+    uncached_preview: Option<(PathBuf, SystemTime)>,
+    preview_target: Option<PathBuf>,
+    preview_latest_in_dir: bool,
+    /// The file whose contents are in `preview_lines`, if any.
+    previewed_file: Option<PathBuf>,
+    /// Set when `preview_lines` are left over from a file that has since
+    /// become unreadable, holding the error.
+    preview_stale: Option<String>,
+    /// A generated file the user asked to preview regardless.
+    forced_preview: Option<PathBuf>,
+    /// Show markup documents as highlighted source instead of formatted.
+    raw_markup: bool,
+    /// Draw the column guide over the preview.
+    show_guide: bool,
+    /// The `bat` binary previews go through, when enabled and installed.
+    bat: Option<PathBuf>,
+    config: Config,
+    extension_display: ExtensionDisplay,
+    swap_panes: bool,
+    two_line_entries: bool,
+    use_icons: bool,
+    show_dir_counts: bool,
+    heatmap: bool,
+    /// Child counts of directories seen in the list, with the mtime they
+    /// were counted at. Filled in while drawing, hence the `RefCell`.
+    dir_counts: RefCell<HashMap<PathBuf, (SystemTime, Option<usize>)>>,
+    /// The cap the current listing was cut short at, if it was.
+    entries_capped: Option<usize>,
+    /// A directory the user asked to read in full despite the cap.
+    uncapped_dir: Option<PathBuf>,
+    state: State,
+    status_message: Option<String>,
+    /// Set by Esc with nothing left to clear; the event loop stops on it.
+    quitting: bool,
+    /// Branch of the git repository `current_dir` is in, if any.
+    git_branch: Option<String>,
+    /// `git status` of the listed entries, when `current_dir` is in a
+    /// repository.
+    git_statuses: Option<HashMap<OsString, git::Status>>,
+    /// The directory `git_statuses` and `git_ignored` were worked out for,
+    /// so a reread of the same one keeps them until git reports again.
+    git_dir: PathBuf,
+    git_tx: Sender<GitReport>,
+    git_rx: Receiver<GitReport>,
+    checksum_job: Option<checksum::Job>,
+    /// Status messages from emits still running in the background.
+    emit_tx: Sender<String>,
+    emit_rx: Receiver<String>,
+    confirm: Option<Confirm>,
+    register: Option<Register>,
+    paste: Option<Paste>,
+    input: Option<Input>,
+    picker: Option<Picker>,
+    recent_cycle: Option<RecentCycle>,
+    show_help: bool,
+    /// When the selection last moved with the preview left behind, which
+    /// `settle_preview` regenerates once it stops moving.
+    preview_dirty: Option<Instant>,
+    syntax_set: Arc<SyntaxSet>,
     theme_set: ThemeSet,
+    /// The syntect theme previews are highlighted with.
+    theme_name: String,
+    /// Bumped whenever the preview changes; highlight chunks carrying an
+    /// older value belong to a file that is no longer shown.
+    highlight_generation: Arc<AtomicU64>,
+    highlight_tx: Sender<HighlightChunk>,
+    highlight_rx: Receiver<HighlightChunk>,
+    highlighting: bool,
+    image_tx: Sender<ImagePreview>,
+    image_rx: Receiver<ImagePreview>,
+    /// Set while an image is being decoded or drawn.
+    drawing_image: bool,
+    /// Bumped by every `refresh_entries`, like `highlight_generation`.
+    listing_generation: Arc<AtomicU64>,
+    listing_tx: Sender<ListingChunk>,
+    listing_rx: Receiver<ListingChunk>,
+    /// How many entries of the directory have been read, counting those
+    /// hidden or filtered out.
+    entries_read: usize,
+    /// Set while the listing thread is still reading `current_dir`.
+    listing_loading: bool,
+    /// An entry to select once it has been read.
+    pending_selection: Option<PathBuf>,
+    /// The entry that was selected in each directory left, by name and by
+    /// index in case it has gone by the time the directory is back.
+    selection_memory: HashMap<PathBuf, (OsString, usize)>,
 }
 
 impl App {
-    fn new() -> io::Result<Self> {
-        let current_dir = env::current_dir()?;
+    /// Opens `start`, a directory or a file to select in its directory,
+    /// or else the working directory.
+    fn new(start: Option<PathBuf>) -> io::Result<Self> {
+        let start = start.map(fs::canonicalize).transpose()?;
+        let (current_dir, start_file) = match start {
+            Some(path) if !path.is_dir() => match path.parent() {
+                Some(parent) => (parent.to_path_buf(), Some(path)),
+                None => (path, None),
+            },
+            Some(dir) => (dir, None),
+            None => (env::current_dir()?, None),
+        };
+        let (config, config_warning) = Config::load();
+        let theme_name = config
+            .theme
+            .clone()
+            .unwrap_or_else(|| DEFAULT_THEME.to_string());
+        let (highlight_tx, highlight_rx) = mpsc::channel();
+        let (image_tx, image_rx) = mpsc::channel();
+        let (listing_tx, listing_rx) = mpsc::channel();
+        let (git_tx, git_rx) = mpsc::channel();
+        let (emit_tx, emit_rx) = mpsc::channel();
         let mut app = Self {
             current_dir,
             entries: Vec::new(),
+            type_filter: None,
+            filter: None,
+            filter_editing: false,
+            filtered_out: Vec::new(),
+            sort_metrics: HashMap::new(),
+            show_hidden: config.show_hidden,
+            hide_ignored: false,
+            git_ignored: None,
+            marked: HashSet::new(),
+            sort_mode: SortMode::Name,
+            sort_descending: false,
             selected: 0,
+            list_offset: 0,
+            list_rows: 0,
+            center_selection: config.center_selection,
             preview_lines: Vec::new(),
             preview_scroll: 0,
-            syntax_set: SyntaxSet::load_defaults_newlines(),
+            preview_hscroll: 0,
+            wrap_enabled: false,
+            preview_focused: false,
+            preview_height: 0,
+            list_area: Rect::default(),
+            preview_area: Rect::default(),
+            last_click: None,
+            preview_page: None,
+            preview_first_line: None,
+            show_line_numbers: false,
+            preview_truncated: false,
+            preview_byte_limit: config.preview_byte_limit,
+            preview_image_size: None,
+            preview_image: None,
+            undecodable_image: None,
+            preview_search: None,
+            preview_cache: lru::Lru::new(PREVIEW_CACHE_SIZE),
+            uncached_preview: None,
+            preview_target: None,
+            preview_latest_in_dir: false,
+            previewed_file: None,
+            preview_stale: None,
+            forced_preview: None,
+            raw_markup: false,
+            show_guide: config.guide,
+            bat: if config.use_bat { find_bat() } else { None },
+            extension_display: config.extension_display,
+            swap_panes: config.swap_panes,
+            two_line_entries: config.two_line_entries,
+            use_icons: config.use_icons,
+            show_dir_counts: config.dir_counts,
+            heatmap: config.heatmap,
+            dir_counts: RefCell::new(HashMap::new()),
+            entries_capped: None,
+            uncapped_dir: None,
+            config,
+            state: State::load(),
+            status_message: config_warning,
+            quitting: false,
+            git_branch: None,
+            git_statuses: None,
+            git_dir: PathBuf::new(),
+            git_tx,
+            git_rx,
+            checksum_job: None,
+            emit_tx,
+            emit_rx,
+            confirm: None,
+            register: None,
+            paste: None,
+            input: None,
+            picker: None,
+            recent_cycle: None,
+            show_help: false,
+            preview_dirty: None,
+            syntax_set: Arc::new(SyntaxSet::load_defaults_newlines()),
             theme_set: ThemeSet::load_defaults(),
+            theme_name,
+            highlight_generation: Arc::new(AtomicU64::new(0)),
+            highlight_tx,
+            highlight_rx,
+            listing_generation: Arc::new(AtomicU64::new(0)),
+            listing_tx,
+            listing_rx,
+            entries_read: 0,
+            listing_loading: false,
+            pending_selection: None,
+            selection_memory: HashMap::new(),
+            highlighting: false,
+            image_tx,
+            image_rx,
+            drawing_image: false,
         };
-        app.refresh_entries()?;
+        if app.status_message.is_none() && !app.theme_set.themes.contains_key(&app.theme_name) {
+            app.status_message = Some(format!(
+                "Unknown theme `{}` in the config, using {}",
+                app.theme_name, DEFAULT_THEME
+            ));
+            app.theme_name = DEFAULT_THEME.to_string();
+        }
+        // An unreadable starting directory still gets a window, if an empty
+        // one, from which `h` can climb out.
+        if let Err(e) = app.refresh_entries() {
+            app.status_message = Some(format!("Cannot read {}: {}", app.current_dir.display(), e));
+        }
+        if let Some(file) = start_file {
+            app.select_path(&file);
+        }
         Ok(app)
     }
 
+    /// Rereads `current_dir`. The first `LISTING_FIRST_CHUNK` entries are
+    /// read right away and the rest streamed in by `receive_entries`, so
+    /// only a directory that can't be opened at all is an error.
     fn refresh_entries(&mut self) -> io::Result<()> {
-        self.entries = fs::read_dir(&self.current_dir)?
+        // One past the cap, to tell whether there is anything left unread.
+        let limit = self
+            .config
+            .max_entries
+            .filter(|_| self.uncapped_dir.as_ref() != Some(&self.current_dir))
+            .map_or(usize::MAX, |cap| cap + 1);
+        let mut dir = fs::read_dir(&self.current_dir)?;
+        let remembered = self.selection_memory.remove(&self.current_dir);
+        if let Some((_, index)) = &remembered {
+            self.selected = *index;
+        }
+        let first: Vec<DirEntry> = dir
+            .by_ref()
             .filter_map(|e| e.ok())
+            .take(limit.min(LISTING_FIRST_CHUNK))
             .collect();
-        self.entries.sort_by(|a, b| {
-            let a_is_dir = a.path().is_dir();
-            let b_is_dir = b.path().is_dir();
-            match (a_is_dir, b_is_dir) {
-                (true, false) => std::cmp::Ordering::Less,
-                (false, true) => std::cmp::Ordering::Greater,
-                _ => a.file_name().cmp(&b.file_name()),
+        let current = Arc::clone(&self.listing_generation);
+        let generation = current.fetch_add(1, Ordering::SeqCst) + 1;
+        self.entries.clear();
+        self.filtered_out.clear();
+        self.sort_metrics.clear();
+        self.entries_read = 0;
+        self.entries_capped = None;
+        self.pending_selection = None;
+        // What git last said about this directory stands until it reports
+        // again, so a reread doesn't flash ignored entries back in.
+        if self.git_dir != self.current_dir {
+            self.git_statuses = None;
+            self.git_ignored = None;
+            self.git_dir = self.current_dir.clone();
+        }
+        if !self.hide_ignored {
+            self.git_ignored = None;
+        }
+        self.listing_loading = first.len() == LISTING_FIRST_CHUNK && limit > first.len();
+        if self.listing_loading {
+            let tx = self.listing_tx.clone();
+            let rest = limit - first.len();
+            thread::spawn(move || {
+                let mut entries = dir.filter_map(|e| e.ok()).take(rest).peekable();
+                loop {
+                    let chunk: Vec<DirEntry> = entries.by_ref().take(LISTING_CHUNK).collect();
+                    let done = entries.peek().is_none();
+                    if current.load(Ordering::SeqCst) != generation
+                        || tx
+                            .send(ListingChunk {
+                                generation,
+                                entries: chunk,
+                                done,
+                            })
+                            .is_err()
+                        || done
+                    {
+                        return;
+                    }
+                }
+            });
+        }
+        self.add_entries(first);
+        if let Some((name, _)) = remembered {
+            match self.entries.iter().position(|e| e.file_name() == name) {
+                Some(idx) => self.selected = idx,
+                None if self.listing_loading => {
+                    self.pending_selection = Some(self.current_dir.join(name))
+                }
+                None => {}
             }
-        });
+        }
         if self.selected >= self.entries.len() {
             self.selected = self.entries.len().saturating_sub(1);
         }
+        self.git_branch = git::current_branch(&self.current_dir);
+        // Forget marks on anything deleted or moved away since.
+        self.marked
+            .retain(|path| fs::symlink_metadata(path).is_ok());
+        if self.git_branch.is_some() {
+            let tx = self.git_tx.clone();
+            let dir = self.current_dir.clone();
+            let hide_ignored = self.hide_ignored;
+            thread::spawn(move || {
+                let _ = tx.send(GitReport {
+                    generation,
+                    statuses: git::statuses(&dir),
+                    ignored: hide_ignored.then(|| git::ignored(&dir)).flatten(),
+                });
+            });
+        } else {
+            self.git_statuses = None;
+            self.git_ignored = None;
+        }
+        self.state.visit(&self.current_dir);
         self.update_preview();
         Ok(())
     }
 
-    fn syntect_to_ratatui_color(color: highlighting::Color) -> Color {
-        Color::Rgb(color.r, color.g, color.b)
+    /// Merges newly read entries into the listing, keeping the selection
+    /// on the same entry, and applies `list.max_entries`.
+    fn add_entries(&mut self, mut read: Vec<DirEntry>) {
+        if let Some(cap) = self.config.max_entries
+            && self.uncapped_dir.as_ref() != Some(&self.current_dir)
+            && self.entries_read + read.len() > cap
+        {
+            read.truncate(cap.saturating_sub(self.entries_read));
+            self.entries_capped = Some(cap);
+        }
+        self.entries_read += read.len();
+        let selected_path = self.entries.get(self.selected).map(|e| e.path());
+        let mut read: Vec<DirEntry> = read
+            .into_iter()
+            .filter(|e| self.show_hidden || !is_hidden(&e.file_name()))
+            .filter(|e| {
+                self.git_ignored
+                    .as_ref()
+                    .is_none_or(|ignored| !ignored.contains(&e.file_name()))
+            })
+            .filter(|e| match self.type_filter {
+                None => true,
+                Some(EntryKind::Directory) => entry_is_dir(e),
+                Some(EntryKind::File) => !entry_is_dir(e),
+            })
+            .collect();
+        // Only the new entries are sorted; the listing already is, so they
+        // are merged into it, and into what the filter hides.
+        self.measure(&read);
+        self.sort(&mut read);
+        let rest = match &self.filter {
+            Some(query) => {
+                let (matched, rest) = read.into_iter().partition(|e| {
+                    fuzzy::match_indices(query, &e.file_name().to_string_lossy()).is_some()
+                });
+                read = matched;
+                rest
+            }
+            None => Vec::new(),
+        };
+        let entries = std::mem::take(&mut self.entries);
+        self.entries = self.merge(entries, read);
+        let filtered_out = std::mem::take(&mut self.filtered_out);
+        self.filtered_out = self.merge(filtered_out, rest);
+        if let Some(idx) =
+            selected_path.and_then(|path| self.entries.iter().position(|e| e.path() == path))
+        {
+            self.selected = idx;
+        }
     }
 
-    fn highlight_content(&self, content: &str, path: &PathBuf) -> Vec<Line<'static>> {
-        let syntax = self
-            .syntax_set
-            .find_syntax_for_file(path)
-            .ok()
-            .flatten()
-            .unwrap_or_else(|| self.syntax_set.find_syntax_plain_text());
-
-        let theme = &self.theme_set.themes["base16-ocean.dark"];
-        let mut highlighter = HighlightLines::new(syntax, theme);
-
-        let mut lines = Vec::new();
-        for line in LinesWithEndings::from(content) {
-            let ranges = highlighter
-                .highlight_line(line, &self.syntax_set)
-                .unwrap_or_default();
-
-            let spans: Vec<Span<'static>> = ranges
-                .into_iter()
-                .map(|(style, text)| {
-                    let fg = Self::syntect_to_ratatui_color(style.foreground);
-                    Span::styled(text.to_string(), Style::default().fg(fg))
-                })
-                .collect();
-
-            lines.push(Line::from(spans));
+    /// Merges in whatever the listing thread has read since the last frame.
+    fn receive_entries(&mut self) {
+        let current = self.listing_generation.load(Ordering::SeqCst);
+        let mut read = Vec::new();
+        while let Ok(chunk) = self.listing_rx.try_recv() {
+            if chunk.generation != current {
+                continue;
+            }
+            read.extend(chunk.entries);
+            if chunk.done {
+                self.listing_loading = false;
+            }
+        }
+        if read.is_empty() {
+            return;
+        }
+        let selected_path = self.entries.get(self.selected).map(|e| e.path());
+        self.add_entries(read);
+        if let Some(path) = self.pending_selection.take()
+            && !self.select_path(&path)
+        {
+            self.pending_selection = Some(path);
+        }
+        if self.entries.get(self.selected).map(|e| e.path()) != selected_path {
+            self.update_preview();
         }
-        lines
     }
 
-    fn update_preview(&mut self) {
-        self.preview_scroll = 0;
-        if let Some(entry) = self.entries.get(self.selected) {
-            let path = entry.path();
-            if path.is_dir() {
-                match fs::read_dir(&path) {
-                    Ok(entries) => {
-                        let mut items: Vec<(String, bool)> = entries
-                            .filter_map(|e| e.ok())
-                            .map(|e| {
-                                let name = e.file_name().to_string_lossy().to_string();
-                                let is_dir = e.path().is_dir();
-                                (name, is_dir)
-                            })
-                            .collect();
-                        items.sort_by(|a, b| a.0.cmp(&b.0));
-
-                        self.preview_lines = items
-                            .into_iter()
-                            .map(|(name, is_dir)| {
-                                let display = if is_dir {
-                                    format!("{}/", name)
-                                } else {
-                                    name
-                                };
-                                let style = if is_dir {
-                                    Style::default().fg(Color::Blue)
-                                } else {
-                                    Style::default()
-                                };
-                                Line::from(Span::styled(display, style))
-                            })
-                            .collect();
-                    }
-                    Err(e) => {
-                        self.preview_lines =
-                            vec![Line::from(format!("Cannot read directory: {}", e))];
-                    }
-                }
-            } else {
-                match fs::read_to_string(&path) {
-                    Ok(content) => {
-                        let truncated: String = content.chars().take(50000).collect();
-                        self.preview_lines = self.highlight_content(&truncated, &path);
-                    }
-                    Err(_) => {
-                        self.preview_lines = vec![Line::from("[Binary file or cannot read]")];
-                    }
-                }
+    /// Takes in what git reported for the current listing, dropping reports
+    /// for earlier ones.
+    fn receive_git(&mut self) {
+        let current = self.listing_generation.load(Ordering::SeqCst);
+        while let Ok(report) = self.git_rx.try_recv() {
+            if report.generation != current {
+                continue;
+            }
+            self.git_statuses = report.statuses;
+            if self.hide_ignored {
+                self.hide_git_ignored(report.ignored);
             }
-        } else {
-            self.preview_lines.clear();
         }
     }
 
-    fn move_up(&mut self) {
-        if self.selected > 0 {
-            self.selected -= 1;
+    /// Takes `ignored` as what the repository ignores and drops it from the
+    /// listing, which was read without knowing.
+    fn hide_git_ignored(&mut self, ignored: Option<HashSet<OsString>>) {
+        self.git_ignored = ignored;
+        let Some(ignored) = &self.git_ignored else {
+            return;
+        };
+        let selected_path = self.entries.get(self.selected).map(|e| e.path());
+        self.entries.retain(|e| !ignored.contains(&e.file_name()));
+        self.filtered_out
+            .retain(|e| !ignored.contains(&e.file_name()));
+        match selected_path
+            .as_ref()
+            .and_then(|path| self.entries.iter().position(|e| &e.path() == path))
+        {
+            Some(idx) => self.selected = idx,
+            None => self.selected = self.selected.min(self.entries.len().saturating_sub(1)),
+        }
+        if self.entries.get(self.selected).map(|e| e.path()) != selected_path {
             self.update_preview();
         }
     }
 
-    fn move_down(&mut self) {
-        if self.selected + 1 < self.entries.len() {
-            self.selected += 1;
-            self.update_preview();
-        }
+    /// What an entry is ordered by: directories first, then its sort metric
+    /// from `sort_metrics`, then its name.
+    fn sort_key(&self, entry: &DirEntry) -> (bool, u128, OsString) {
+        let name = entry.file_name();
+        let metric = self.sort_metrics.get(&name).copied().unwrap_or(0);
+        (!entry_is_dir(entry), metric, name)
     }
 
-    fn enter_directory(&mut self) -> io::Result<()> {
-        if let Some(entry) = self.entries.get(self.selected) {
-            let path = entry.path();
-            if path.is_dir() {
-                self.current_dir = path;
-                self.selected = 0;
-                self.refresh_entries()?;
+    /// Orders two sort keys, reversing all but the directories-first part
+    /// when sorting descending.
+    fn compare_keys(
+        &self,
+        a: &(bool, u128, OsString),
+        b: &(bool, u128, OsString),
+    ) -> std::cmp::Ordering {
+        a.0.cmp(&b.0).then_with(|| {
+            let order = (a.1, &a.2).cmp(&(b.1, &b.2));
+            if self.sort_descending {
+                order.reverse()
+            } else {
+                order
             }
-        }
-        Ok(())
+        })
     }
 
-    fn go_parent(&mut self) -> io::Result<()> {
-        if let Some(parent) = self.current_dir.parent() {
-            let old_dir = self.current_dir.clone();
-            self.current_dir = parent.to_path_buf();
-            self.refresh_entries()?;
-            if let Some(idx) = self.entries.iter().position(|e| e.path() == old_dir) {
-                self.selected = idx;
-                self.update_preview();
+    /// Records the sort metric of `entries` in `sort_metrics`. Sorting by
+    /// name needs none, so nothing is stat'ed then.
+    fn measure(&mut self, entries: &[DirEntry]) {
+        let mode = self.sort_mode;
+        if mode == SortMode::Name {
+            return;
+        }
+        for entry in entries {
+            // `DirEntry::file_type` is usually known without a stat; the
+            // metadata isn't.
+            if mode == SortMode::Size && entry_is_dir(entry) {
+                continue;
             }
+            let metadata = entry.metadata().ok();
+            let metric = match mode {
+                SortMode::Name => 0,
+                SortMode::Size => metadata.map_or(0, |m| m.len() as u128),
+                SortMode::ModifiedTime => metadata
+                    .and_then(|m| m.modified().ok())
+                    .and_then(|t| t.duration_since(SystemTime::UNIX_EPOCH).ok())
+                    .map_or(0, |d| d.as_nanos()),
+            };
+            self.sort_metrics.insert(entry.file_name(), metric);
         }
-        Ok(())
     }
 
-    fn scroll_preview_down(&mut self, amount: u16) {
-        let max_scroll = (self.preview_lines.len() as u16).saturating_sub(1);
-        self.preview_scroll = (self.preview_scroll + amount).min(max_scroll);
+    /// Sorts already measured `entries` by `sort_key`.
+    fn sort(&self, entries: &mut Vec<DirEntry>) {
+        let mut keyed: Vec<_> = std::mem::take(entries)
+            .into_iter()
+            .map(|e| (self.sort_key(&e), e))
+            .collect();
+        keyed.sort_by(|a, b| self.compare_keys(&a.0, &b.0));
+        entries.extend(keyed.into_iter().map(|(_, e)| e));
     }
 
-    fn scroll_preview_up(&mut self, amount: u16) {
-        self.preview_scroll = self.preview_scroll.saturating_sub(amount);
+    /// Merges two sorted lists of entries into one.
+    fn merge(&self, a: Vec<DirEntry>, b: Vec<DirEntry>) -> Vec<DirEntry> {
+        if a.is_empty() || b.is_empty() {
+            return if a.is_empty() { b } else { a };
+        }
+        let mut merged = Vec::with_capacity(a.len() + b.len());
+        let mut a = a.into_iter().map(|e| (self.sort_key(&e), e)).peekable();
+        let mut b = b.into_iter().map(|e| (self.sort_key(&e), e)).peekable();
+        while let (Some(x), Some(y)) = (a.peek(), b.peek()) {
+            let next = if self.compare_keys(&x.0, &y.0).is_le() {
+                a.next()
+            } else {
+                b.next()
+            };
+            merged.extend(next.map(|(_, e)| e));
+        }
+        merged.extend(a.map(|(_, e)| e));
+        merged.extend(b.map(|(_, e)| e));
+        merged
     }
 
-    fn get_list_items(&self) -> Vec<ListItem<'_>> {
-        self.entries
-            .iter()
-            .map(|entry| {
-                let name = entry.file_name().to_string_lossy().to_string();
-                let is_dir = entry.path().is_dir();
-                let display = if is_dir {
-                    format!("{}/", name)
-                } else {
-                    name
-                };
-                let style = if is_dir {
-                    Style::default().fg(Color::Blue)
-                } else {
-                    Style::default()
-                };
-                ListItem::new(display).style(style)
-            })
-            .collect()
+    /// Sorts the whole listing from scratch, for a new sort order.
+    fn sort_entries(&mut self) {
+        self.sort_metrics.clear();
+        let mut entries = std::mem::take(&mut self.entries);
+        self.measure(&entries);
+        self.sort(&mut entries);
+        self.entries = entries;
     }
-}
-
-fn main() -> io::Result<()> {
-    enable_raw_mode()?;
-    stdout().execute(EnterAlternateScreen)?;
 
-    let backend = CrosstermBackend::new(stdout());
-    let mut terminal = Terminal::new(backend)?;
+    /// Re-sorts the listing in place after the sort order changed, keeping
+    /// the same entry selected.
+    fn resort(&mut self) {
+        let selected_path = self.entries.get(self.selected).map(|e| e.path());
+        self.entries.append(&mut self.filtered_out);
+        self.sort_entries();
+        self.split_by_filter();
+        if let Some(idx) =
+            selected_path.and_then(|path| self.entries.iter().position(|e| e.path() == path))
+        {
+            self.selected = idx;
+        }
+        let direction = if self.sort_descending {
+            "descending"
+        } else {
+            "ascending"
+        };
+        self.status_message = Some(format!(
+            "Sorted by {}, {}",
+            self.sort_mode.name(),
+            direction
+        ));
+    }
 
-    let mut app = App::new()?;
-    let mut list_state = ListState::default();
-    list_state.select(Some(app.selected));
+    fn cycle_sort_mode(&mut self) {
+        self.sort_mode = self.sort_mode.next();
+        self.resort();
+    }
 
-    loop {
-        terminal.draw(|frame| {
-            let chunks = Layout::default()
-                .direction(Direction::Horizontal)
-                .constraints([Constraint::Percentage(40), Constraint::Percentage(60)])
-                .split(frame.area());
+    fn toggle_sort_direction(&mut self) {
+        self.sort_descending = !self.sort_descending;
+        self.resort();
+    }
 
-            let items = app.get_list_items();
-            let list = List::new(items)
-                .block(
-                    Block::default()
-                        .title(app.current_dir.to_string_lossy().to_string())
-                        .borders(Borders::ALL),
-                )
-                .highlight_style(
-                    Style::default()
-                        .bg(Color::Blue)
-                        .fg(Color::White)
-                        .add_modifier(Modifier::BOLD),
-                )
-                .highlight_symbol("> ");
+    /// Moves the entries the filter query doesn't match out of `entries`.
+    fn split_by_filter(&mut self) {
+        let Some(query) = &self.filter else {
+            return;
+        };
+        let (matched, rest): (Vec<_>, Vec<_>) = std::mem::take(&mut self.entries)
+            .into_iter()
+            .partition(|e| fuzzy::match_indices(query, &e.file_name().to_string_lossy()).is_some());
+        self.entries = matched;
+        self.filtered_out.extend(rest);
+    }
 
-            list_state.select(Some(app.selected));
-            frame.render_stateful_widget(list, chunks[0], &mut list_state);
+    /// Re-filters the listing after the query changed. The selection stays
+    /// put if it still matches and moves to the first match otherwise.
+    fn apply_filter(&mut self) {
+        let selected_path = self.entries.get(self.selected).map(|e| e.path());
+        let (entries, filtered_out) = (
+            std::mem::take(&mut self.entries),
+            std::mem::take(&mut self.filtered_out),
+        );
+        self.entries = self.merge(entries, filtered_out);
+        self.split_by_filter();
+        self.selected = selected_path
+            .and_then(|path| self.entries.iter().position(|e| e.path() == path))
+            .unwrap_or(0);
+        self.update_preview();
+    }
 
-            let preview_title = if let Some(entry) = app.entries.get(app.selected) {
-                entry.file_name().to_string_lossy().to_string()
-            } else {
-                "Preview".to_string()
-            };
+    /// Starts typing a filter query, picking up the current one if set.
+    fn start_filter(&mut self) {
+        self.filter.get_or_insert_with(String::new);
+        self.filter_editing = true;
+    }
 
-            let preview = Paragraph::new(app.preview_lines.clone())
-                .block(Block::default().title(preview_title).borders(Borders::ALL))
-                .scroll((app.preview_scroll, 0));
+    fn clear_filter(&mut self) {
+        self.filter = None;
+        self.filter_editing = false;
+        self.apply_filter();
+    }
 
-            frame.render_widget(preview, chunks[1]);
-        })?;
+    /// Esc: undoes the innermost of the preview search, the preview focus,
+    /// the filter and the type filter, or with none of them left, quits.
+    fn escape(&mut self) {
+        if self.preview_search.is_some() {
+            self.preview_search = None;
+        } else if self.preview_focused {
+            self.preview_focused = false;
+        } else if self.filter.is_some() {
+            self.clear_filter();
+        } else if self.type_filter.is_some() {
+            self.toggle_type_filter(None);
+        } else {
+            self.quitting = true;
+        }
+    }
 
-        if event::poll(std::time::Duration::from_millis(100))? {
-            if let Event::Key(key) = event::read()? {
-                if key.kind == KeyEventKind::Press {
-                    match key.code {
-                        KeyCode::Char('q') | KeyCode::Esc => break,
-                        KeyCode::Char('j') | KeyCode::Down => app.move_down(),
-                        KeyCode::Char('k') | KeyCode::Up => app.move_up(),
-                        KeyCode::Char('l') | KeyCode::Right | KeyCode::Enter => {
-                            app.enter_directory()?;
-                        }
-                        KeyCode::Char('h') | KeyCode::Left => {
-                            app.go_parent()?;
-                        }
-                        KeyCode::Char('n') => app.scroll_preview_down(15),
-                        KeyCode::Char('p') => app.scroll_preview_up(15),
-                        _ => {}
-                    }
+    fn handle_filter_key(&mut self, code: KeyCode) {
+        let Some(query) = &mut self.filter else {
+            return;
+        };
+        match code {
+            KeyCode::Char(c) => query.push(c),
+            KeyCode::Backspace => {
+                query.pop();
+            }
+            KeyCode::Enter => {
+                self.filter_editing = false;
+                if query.is_empty() {
+                    self.filter = None;
                 }
+                return;
             }
+            KeyCode::Esc => return self.clear_filter(),
+            _ => return,
         }
+        self.apply_filter();
     }
 
-    disable_raw_mode()?;
-    stdout().execute(LeaveAlternateScreen)?;
-    Ok(())
+    fn syntect_to_ratatui_color(color: highlighting::Color) -> Color {
+        Color::Rgb(color.r, color.g, color.b)
+    }
+
+    /// Shows `content` as plain text right away and hands it to a
+    /// background thread, whose highlighted chunks replace the plain lines
+    /// as they arrive (see `receive_highlights`). With `bat`, that thread
+    /// asks bat for the whole of `path` first and only falls back to
+    /// syntect if bat fails.
+    fn start_highlight(&mut self, content: &str, path: &PathBuf, bat: Option<PathBuf>) {
+        self.preview_lines = LinesWithEndings::from(content)
+            .map(|line| Line::raw(line.trim_end_matches(['\n', '\r']).to_string()))
+            .collect();
+        self.preview_first_line = Some(self.preview_page.as_ref().map_or(1, |p| p.first_line));
+
+        let syntax = self
+            .syntax_set
+            .find_syntax_for_file(path)
+            .ok()
+            .flatten()
+            .unwrap_or_else(|| self.syntax_set.find_syntax_plain_text())
+            .clone();
+        let themes = &self.theme_set.themes;
+        let theme = themes
+            .get(&self.theme_name)
+            .or_else(|| themes.get(DEFAULT_THEME))
+            .or_else(|| themes.values().next())
+            .cloned()
+            .unwrap_or_default();
+        let syntax_set = Arc::clone(&self.syntax_set);
+        let current = Arc::clone(&self.highlight_generation);
+        let generation = current.fetch_add(1, Ordering::SeqCst) + 1;
+        let tx = self.highlight_tx.clone();
+        let content = content.to_string();
+        let path = path.clone();
+        self.highlighting = true;
+
+        thread::spawn(move || {
+            let lines = content.lines().count();
+            if let Some(lines) = bat.and_then(|bat| bat_preview(&bat, &path, lines)) {
+                let _ = tx.send(HighlightChunk {
+                    generation,
+                    start: 0,
+                    lines,
+                    done: true,
+                });
+                return;
+            }
+            let mut highlighter = HighlightLines::new(&syntax, &theme);
+            let mut start = 0;
+            let mut lines = Vec::with_capacity(HIGHLIGHT_CHUNK_LINES);
+            for line in LinesWithEndings::from(&content) {
+                let ranges = highlighter
+                    .highlight_line(line, &syntax_set)
+                    .unwrap_or_default();
+
+                let spans: Vec<Span<'static>> = ranges
+                    .into_iter()
+                    .map(|(style, text)| {
+                        let fg = Self::syntect_to_ratatui_color(style.foreground);
+                        Span::styled(text.to_string(), Style::default().fg(fg))
+                    })
+                    .collect();
+
+                lines.push(Line::from(spans));
+                if lines.len() == HIGHLIGHT_CHUNK_LINES {
+                    if current.load(Ordering::SeqCst) != generation {
+                        return;
+                    }
+                    let chunk = HighlightChunk {
+                        generation,
+                        start,
+                        lines: std::mem::take(&mut lines),
+                        done: false,
+                    };
+                    if tx.send(chunk).is_err() {
+                        return;
+                    }
+                    start += HIGHLIGHT_CHUNK_LINES;
+                }
+            }
+            let _ = tx.send(HighlightChunk {
+                generation,
+                start,
+                lines,
+                done: true,
+            });
+        });
+    }
+
+    /// Swaps in any highlighted lines that have arrived for the current
+    /// preview, dropping chunks left over from earlier ones.
+    fn receive_highlights(&mut self) {
+        let current = self.highlight_generation.load(Ordering::SeqCst);
+        while let Ok(chunk) = self.highlight_rx.try_recv() {
+            if chunk.generation != current {
+                continue;
+            }
+            for (slot, line) in self
+                .preview_lines
+                .iter_mut()
+                .skip(chunk.start)
+                .zip(chunk.lines)
+            {
+                *slot = line;
+            }
+            if chunk.done {
+                self.highlighting = false;
+                self.cache_preview();
+            }
+        }
+    }
+
+    /// Decodes the image at `path`, unless it is given already decoded, and
+    /// draws it for the pane on a background thread, as large pictures take
+    /// a while (see `receive_image`).
+    fn start_image(&mut self, path: &Path, image: Option<Arc<image::Image>>) {
+        let (columns, rows) = self.preview_cells();
+        self.preview_image_size = Some((columns, rows));
+        let current = Arc::clone(&self.highlight_generation);
+        let generation = current.fetch_add(1, Ordering::SeqCst) + 1;
+        let tx = self.image_tx.clone();
+        let path = path.to_path_buf();
+        self.drawing_image = true;
+
+        thread::spawn(move || {
+            let image = image.or_else(|| image::decode(&path).map(Arc::new));
+            if current.load(Ordering::SeqCst) != generation {
+                return;
+            }
+            let lines = image
+                .as_deref()
+                .map(|image| image::draw(image, columns, rows))
+                .unwrap_or_default();
+            let _ = tx.send(ImagePreview {
+                generation,
+                image,
+                lines,
+            });
+        });
+    }
+
+    /// Shows an image once it is drawn. One that couldn't be decoded is
+    /// previewed again the way any other file would be.
+    fn receive_image(&mut self) {
+        let current = self.highlight_generation.load(Ordering::SeqCst);
+        while let Ok(preview) = self.image_rx.try_recv() {
+            if preview.generation != current {
+                continue;
+            }
+            self.drawing_image = false;
+            match preview.image {
+                Some(image) => {
+                    self.preview_lines = preview.lines;
+                    self.preview_image = Some(image);
+                }
+                None => {
+                    self.undecodable_image = self.previewed_file.clone();
+                    self.update_preview();
+                    return;
+                }
+            }
+        }
+    }
+
+    /// Draws the image shown again once the pane has changed size. One
+    /// still being decoded is drawn for the new size when it is done.
+    fn resize_image(&mut self) {
+        if let Some(path) = self.previewed_file.clone()
+            && !self.drawing_image
+        {
+            let image = self.preview_image.clone();
+            self.start_image(&path, image);
+        }
+    }
+
+    /// Keeps the finished preview on screen for coming back to.
+    fn cache_preview(&mut self) {
+        if let Some(key) = self.uncached_preview.take() {
+            self.preview_cache.insert(
+                key,
+                CachedPreview {
+                    lines: self.preview_lines.clone(),
+                    first_line: self.preview_first_line,
+                    truncated: self.preview_truncated,
+                },
+            );
+        }
+    }
+
+    fn update_preview(&mut self) {
+        self.preview_dirty = None;
+        self.highlight_generation.fetch_add(1, Ordering::SeqCst);
+        self.highlighting = false;
+        self.preview_scroll = 0;
+        self.preview_hscroll = 0;
+        self.preview_truncated = false;
+        self.preview_search = None;
+        self.uncached_preview = None;
+        self.preview_image_size = None;
+        self.preview_image = None;
+        self.drawing_image = false;
+        self.preview_target = None;
+        self.preview_stale = None;
+        let last_file = self.previewed_file.take();
+        let last_lines = std::mem::take(&mut self.preview_lines);
+        // Which page of a big file was shown, to keep with its lines.
+        let last_page = self.preview_page.take();
+        let last_first_line = self.preview_first_line.take();
+        let Some(path) = self.entries.get(self.selected).map(|e| e.path()) else {
+            return;
+        };
+        let file = if path.is_dir() {
+            let latest = if self.preview_latest_in_dir {
+                latest_modified_file(&path, self.show_hidden)
+            } else {
+                None
+            };
+            match latest {
+                Some(latest) => {
+                    self.preview_target = Some(latest.clone());
+                    latest
+                }
+                None => {
+                    self.preview_dir_listing(&path);
+                    return;
+                }
+            }
+        } else {
+            path
+        };
+        if fs::symlink_metadata(&file).is_ok_and(|m| m.file_type().is_symlink())
+            && fs::metadata(&file).is_err()
+        {
+            let target = fs::read_link(&file).unwrap_or_default();
+            self.preview_lines = vec![
+                Line::from(Span::styled(
+                    "[Broken symbolic link]",
+                    Style::default().fg(Color::Red),
+                )),
+                Line::from(format!("-> {}", target.display())),
+            ];
+            return;
+        }
+        if self.forced_preview.as_ref() != Some(&file)
+            && self
+                .config
+                .generated_files
+                .iter()
+                .any(|pattern| glob::is_match(pattern, &file))
+        {
+            self.preview_lines = vec![
+                Line::from(Span::styled(
+                    "[Generated file, preview suppressed]",
+                    Style::default().fg(Color::DarkGray),
+                )),
+                Line::from(Span::styled(
+                    "Press F to preview it anyway.",
+                    Style::default().fg(Color::DarkGray),
+                )),
+            ];
+            return;
+        }
+        let key = fs::metadata(&file)
+            .and_then(|m| m.modified())
+            .ok()
+            .map(|modified| (file.clone(), modified));
+        if let Some(cached) = key.as_ref().and_then(|key| self.preview_cache.get(key)) {
+            self.preview_lines = cached.lines.clone();
+            self.preview_first_line = cached.first_line;
+            self.preview_truncated = cached.truncated;
+            self.previewed_file = Some(file);
+            return;
+        }
+        match self.preview_file(&file) {
+            Ok(()) => {
+                self.previewed_file = Some(file);
+                // Paged files are left out, as only one page is ever in
+                // hand, and so are images, which are drawn for the pane.
+                if self.preview_page.is_none() && self.preview_image_size.is_none() {
+                    self.uncached_preview = key;
+                    if !self.highlighting {
+                        self.cache_preview();
+                    }
+                }
+            }
+            // A file that was readable a moment ago may just be mid-rewrite,
+            // so optionally keep what it last showed rather than the error.
+            Err(e) if self.config.keep_stale_preview && last_file.as_ref() == Some(&file) => {
+                self.preview_lines = last_lines;
+                self.preview_page = last_page;
+                self.preview_first_line = last_first_line;
+                self.preview_stale = Some(e.to_string());
+                self.previewed_file = Some(file);
+            }
+            Err(e) => {
+                self.preview_page = None;
+                self.preview_lines = vec![Line::from(format!("Cannot read file: {}", e))];
+            }
+        }
+    }
+
+    fn preview_dir_listing(&mut self, path: &PathBuf) {
+        match fs::read_dir(path) {
+            Ok(entries) => {
+                let mut items: Vec<(String, bool)> = entries
+                    .filter_map(|e| e.ok())
+                    .filter(|e| self.show_hidden || !is_hidden(&e.file_name()))
+                    .map(|e| {
+                        let name = e.file_name().to_string_lossy().to_string();
+                        let is_dir = e.path().is_dir();
+                        (name, is_dir)
+                    })
+                    .collect();
+                items.sort_by(|a, b| a.0.cmp(&b.0));
+
+                self.preview_lines = items
+                    .into_iter()
+                    .map(|(name, is_dir)| {
+                        let mut display = if is_dir {
+                            format!("{}/", name)
+                        } else {
+                            name
+                        };
+                        if self.use_icons {
+                            let icon =
+                                icons::icon(OsStr::new(display.trim_end_matches('/')), is_dir);
+                            display = format!("{} {}", icon, display);
+                        }
+                        let style = if is_dir {
+                            Style::default().fg(Color::Blue)
+                        } else {
+                            Style::default()
+                        };
+                        Line::from(Span::styled(display, style))
+                    })
+                    .collect();
+            }
+            Err(e) => {
+                self.preview_lines = vec![Line::from(format!("Cannot read directory: {}", e))];
+            }
+        }
+    }
+
+    /// The width and height inside the preview pane's border, as last drawn.
+    fn preview_cells(&self) -> (u16, u16) {
+        (
+            self.preview_area.width.saturating_sub(2),
+            self.preview_height,
+        )
+    }
+
+    /// Fills the preview with `path`. Binary files get a placeholder; only a
+    /// failure to read the file at all is returned as an error.
+    fn preview_file(&mut self, path: &PathBuf) -> io::Result<()> {
+        if let Some(info) = encrypted::detect(path) {
+            self.preview_lines = vec![
+                Line::from(Span::styled(
+                    "[Encrypted file]",
+                    Style::default()
+                        .fg(Color::Yellow)
+                        .add_modifier(Modifier::BOLD),
+                )),
+                Line::from(info.format),
+            ];
+            self.preview_lines.extend(info.details.into_iter().map(Line::from));
+            return Ok(());
+        }
+        let len = fs::metadata(path).map_or(0, |m| m.len());
+        let mut head = Vec::new();
+        File::open(path)?
+            .take(HEX_DUMP_BYTES as u64)
+            .read_to_end(&mut head)?;
+        if image::is_image(&head) && self.undecodable_image.take().as_ref() != Some(path) {
+            self.preview_lines = vec![Line::from(Span::styled(
+                "Decoding image...",
+                Style::default().fg(Color::DarkGray),
+            ))];
+            self.start_image(path, None);
+            return Ok(());
+        }
+        if head[..head.len().min(BINARY_SNIFF_BYTES)].contains(&0) {
+            self.preview_lines = hexdump::render(&head, len);
+            return Ok(());
+        }
+        if len > PREVIEW_PAGE_BYTES {
+            self.preview_page = Some(PreviewPage {
+                page: 0,
+                page_count: len.div_ceil(PREVIEW_PAGE_BYTES),
+                first_line: 1,
+                line_count: 0,
+            });
+            self.load_preview_page(path, 0)?;
+            return Ok(());
+        }
+        // Anything too small to page fits in the head already read.
+        let limit = self.preview_byte_limit;
+        let truncated = head.len() > limit;
+        let bytes = if truncated {
+            // Cut after the last whole line, or between characters if the
+            // first line alone is over the limit.
+            let cut = head[..limit]
+                .iter()
+                .rposition(|&b| b == b'\n')
+                .map_or_else(|| complete_utf8_len(&head[..limit]), |i| i + 1);
+            &head[..cut]
+        } else {
+            &head[..]
+        };
+        let Ok(content) = std::str::from_utf8(bytes) else {
+            self.preview_lines = hexdump::render(&head, len);
+            return Ok(());
+        };
+        let formatted = if diff::is_diff(path, content) {
+            diff::render(content)
+        } else {
+            markup::Format::from_path(path)
+                .filter(|_| !self.raw_markup)
+                .and_then(|format| markup::render(format, content))
+        };
+        if let Some(lines) = formatted {
+            self.preview_lines = lines;
+        } else {
+            self.start_highlight(content, path, self.bat.clone());
+        }
+        if truncated {
+            self.preview_lines.push(Line::from(Span::styled(
+                "-- truncated --",
+                Style::default().fg(Color::DarkGray),
+            )));
+            self.preview_truncated = true;
+        }
+        Ok(())
+    }
+
+    /// The file the preview pane is showing: the selected entry itself, or
+    /// the file standing in for a selected directory.
+    fn preview_path(&self) -> Option<PathBuf> {
+        self.preview_target
+            .clone()
+            .or_else(|| self.entries.get(self.selected).map(|e| e.path()))
+    }
+
+    /// Replaces the preview with `page` of `path`. Returns `false` when the
+    /// page holds no line start of its own and has nothing to show. A page
+    /// that can't be read leaves the preview as it was.
+    fn load_preview_page(&mut self, path: &PathBuf, page: u64) -> io::Result<bool> {
+        let bytes = read_page(path, page)?;
+        if bytes.is_empty() {
+            return Ok(false);
+        }
+        if let Some(paged) = &mut self.preview_page {
+            // Pages are only ever stepped through one way or the other (the
+            // ones skipped hold no lines), so the new page's first line
+            // follows from the line counts of this page and the old one.
+            let line_count = bytes.iter().filter(|&&b| b == b'\n').count()
+                + usize::from(!bytes.ends_with(b"\n"));
+            if page > paged.page {
+                paged.first_line += paged.line_count;
+            } else if page < paged.page {
+                paged.first_line = paged.first_line.saturating_sub(line_count).max(1);
+            }
+            paged.page = page;
+            paged.line_count = line_count;
+        }
+        self.preview_first_line = None;
+        self.preview_stale = None;
+        if bytes.contains(&0) {
+            self.preview_lines = vec![Line::from("[Binary file or cannot read]")];
+        } else {
+            let content = String::from_utf8_lossy(&bytes);
+            self.start_highlight(&content, path, None);
+        }
+        self.preview_scroll = 0;
+        if let Some(search) = &mut self.preview_search {
+            search.matches = search::find(&self.preview_lines, &search.query);
+            search.current = 0;
+        }
+        Ok(true)
+    }
+
+    /// Deals with a page of the file shown failing to read, as
+    /// `update_preview` does with the file as a whole.
+    fn page_failed(&mut self, e: io::Error) {
+        if self.config.keep_stale_preview {
+            self.preview_stale = Some(e.to_string());
+        } else {
+            self.highlight_generation.fetch_add(1, Ordering::SeqCst);
+            self.highlighting = false;
+            self.preview_lines = vec![Line::from(format!("Cannot read file: {}", e))];
+            self.preview_scroll = 0;
+        }
+    }
+
+    /// Moves the preview forward a screenful, continuing onto the next page
+    /// of a paged file once the current one is exhausted.
+    fn page_preview_down(&mut self) {
+        let height = self.preview_height.max(1);
+        if usize::from(self.preview_scroll + height) < self.preview_lines.len() {
+            self.preview_scroll += height;
+            return;
+        }
+        let Some((page, page_count)) = self.preview_page.as_ref().map(|p| (p.page, p.page_count))
+        else {
+            return;
+        };
+        let Some(path) = self.preview_path() else {
+            return;
+        };
+        for next in page + 1..page_count {
+            match self.load_preview_page(&path, next) {
+                Ok(false) => {}
+                Ok(true) => break,
+                Err(e) => return self.page_failed(e),
+            }
+        }
+    }
+
+    /// Moves the preview back a screenful, landing on the last screenful of
+    /// the previous page when already at the top of this one.
+    fn page_preview_up(&mut self) {
+        let height = self.preview_height.max(1);
+        if self.preview_scroll > 0 {
+            self.preview_scroll = self.preview_scroll.saturating_sub(height);
+            return;
+        }
+        let Some(page) = self.preview_page.as_ref().map(|p| p.page) else {
+            return;
+        };
+        let Some(path) = self.preview_path() else {
+            return;
+        };
+        for prev in (0..page).rev() {
+            match self.load_preview_page(&path, prev) {
+                Ok(false) => {}
+                Ok(true) => {
+                    let lines = self.preview_lines.len() as u16;
+                    self.preview_scroll = lines.saturating_sub(1) / height * height;
+                    break;
+                }
+                Err(e) => return self.page_failed(e),
+            }
+        }
+    }
+
+    /// Previews the current file even if it matches `preview.generated`.
+    fn force_preview(&mut self) {
+        self.forced_preview = self.preview_path();
+        self.update_preview();
+    }
+
+    /// Switches documents with light formatting (reStructuredText, AsciiDoc,
+    /// Org) between the formatted view and their highlighted source.
+    fn toggle_raw_markup(&mut self) {
+        self.raw_markup = !self.raw_markup;
+        self.preview_cache.clear();
+        self.update_preview();
+    }
+
+    /// Moves on to the next highlighting theme, in name order.
+    fn cycle_theme(&mut self) {
+        let names: Vec<&String> = self.theme_set.themes.keys().collect();
+        let next = names
+            .iter()
+            .position(|name| **name == self.theme_name)
+            .map_or(0, |i| (i + 1) % names.len());
+        let Some(name) = names.get(next) else {
+            return;
+        };
+        self.theme_name = name.to_string();
+        self.status_message = Some(format!("Theme: {}", self.theme_name));
+        self.preview_cache.clear();
+        self.update_preview();
+    }
+
+    fn toggle_preview_latest_in_dir(&mut self) {
+        self.preview_latest_in_dir = !self.preview_latest_in_dir;
+        self.update_preview();
+    }
+
+    fn move_to_top(&mut self) {
+        if !self.entries.is_empty() {
+            self.selected = 0;
+            self.update_preview();
+        }
+    }
+
+    fn move_to_bottom(&mut self) {
+        if !self.entries.is_empty() {
+            self.selected = self.entries.len() - 1;
+            self.update_preview();
+        }
+    }
+
+    fn move_up(&mut self) {
+        if self.selected > 0 {
+            self.selected -= 1;
+            self.pending_selection = None;
+            self.preview_dirty = Some(Instant::now());
+        }
+    }
+
+    fn move_down(&mut self) {
+        if self.selected + 1 < self.entries.len() {
+            self.selected += 1;
+            self.pending_selection = None;
+            self.preview_dirty = Some(Instant::now());
+        }
+    }
+
+    /// Moves the selection a page of rows down, stopping at the last entry.
+    fn page_down(&mut self) {
+        let last = self.entries.len().saturating_sub(1);
+        if self.selected < last {
+            self.selected = (self.selected + self.list_rows.max(1)).min(last);
+            self.pending_selection = None;
+            self.preview_dirty = Some(Instant::now());
+        }
+    }
+
+    fn page_up(&mut self) {
+        if self.selected > 0 {
+            self.selected = self.selected.saturating_sub(self.list_rows.max(1));
+            self.pending_selection = None;
+            self.preview_dirty = Some(Instant::now());
+        }
+    }
+
+    /// Regenerates a preview left behind by `move_up` / `move_down` once
+    /// the selection has been still for `PREVIEW_DEBOUNCE`. `force` does it
+    /// right away.
+    fn settle_preview(&mut self, force: bool) {
+        if let Some(moved) = self.preview_dirty
+            && (force || moved.elapsed() >= PREVIEW_DEBOUNCE)
+        {
+            self.update_preview();
+        }
+    }
+
+    /// The key entries are bucketed by for `{` / `}`: directories and files
+    /// are sorted apart, so each side gets its own run of first letters.
+    fn letter_group(entry: &DirEntry) -> (bool, Option<char>) {
+        let first = entry
+            .file_name()
+            .to_string_lossy()
+            .chars()
+            .next()
+            .map(|c| c.to_ascii_lowercase());
+        (entry.path().is_dir(), first)
+    }
+
+    /// Letter groups only line up when the listing is in name order; says
+    /// so otherwise.
+    fn sorted_by_name(&mut self) -> bool {
+        let by_name = self.sort_mode == SortMode::Name;
+        if !by_name {
+            self.status_message = Some("Letter groups need sorting by name (s)".into());
+        }
+        by_name
+    }
+
+    /// Jumps to the first entry whose first letter differs from the selected
+    /// one's, like flipping to the next letter of an address book.
+    fn next_letter_group(&mut self) {
+        if !self.sorted_by_name() {
+            return;
+        }
+        let Some(current) = self.entries.get(self.selected).map(Self::letter_group) else {
+            return;
+        };
+        if let Some(offset) = self.entries[self.selected..]
+            .iter()
+            .position(|e| Self::letter_group(e) != current)
+        {
+            self.selected += offset;
+            self.update_preview();
+        }
+    }
+
+    /// Jumps to the start of the current letter group, or to the start of
+    /// the previous group when already there.
+    fn prev_letter_group(&mut self) {
+        if !self.sorted_by_name() {
+            return;
+        }
+        let group_start = |entries: &[DirEntry], idx: usize| {
+            let key = Self::letter_group(&entries[idx]);
+            entries[..idx]
+                .iter()
+                .rposition(|e| Self::letter_group(e) != key)
+                .map_or(0, |i| i + 1)
+        };
+        if self.selected >= self.entries.len() {
+            return;
+        }
+        let mut target = group_start(&self.entries, self.selected);
+        if target == self.selected && target > 0 {
+            target = group_start(&self.entries, target - 1);
+        }
+        if target != self.selected {
+            self.selected = target;
+            self.update_preview();
+        }
+    }
+
+    /// Lists `dir` instead of the current directory. When `dir` can't be
+    /// read, nothing changes apart from the error landing in the status
+    /// line; returns whether the switch happened.
+    fn change_dir(&mut self, dir: PathBuf) -> bool {
+        if let Some(entry) = self.entries.get(self.selected) {
+            self.selection_memory
+                .insert(self.current_dir.clone(), (entry.file_name(), self.selected));
+        }
+        let old_dir = std::mem::replace(&mut self.current_dir, dir);
+        let old_selected = std::mem::replace(&mut self.selected, 0);
+        // A filter query belongs to the directory it was typed in.
+        let old_filter = self.filter.take();
+        // `refresh_entries` fails before touching anything but these.
+        match self.refresh_entries() {
+            Ok(()) => {
+                self.filter_editing = false;
+                true
+            }
+            Err(e) => {
+                self.status_message =
+                    Some(format!("Cannot open {}: {}", self.current_dir.display(), e));
+                self.selection_memory.remove(&old_dir);
+                self.current_dir = old_dir;
+                self.selected = old_selected;
+                self.filter = old_filter;
+                false
+            }
+        }
+    }
+
+    fn enter_directory(&mut self) {
+        let Some(entry) = self.entries.get(self.selected) else {
+            return;
+        };
+        let path = entry.path();
+        if !path.is_dir() {
+            return;
+        }
+        // A link back up the tree (`ln -s . loop`) could be followed forever.
+        if entry.file_type().is_ok_and(|t| t.is_symlink())
+            && let Ok(target) = fs::canonicalize(&path)
+            && fs::canonicalize(&self.current_dir).is_ok_and(|dir| dir.starts_with(&target))
+        {
+            self.status_message = Some(format!(
+                "Not following {}: it links back to {}",
+                entry.file_name().to_string_lossy(),
+                target.display()
+            ));
+            return;
+        }
+        self.change_dir(path);
+    }
+
+    fn go_parent(&mut self) {
+        let Some(parent) = self.current_dir.parent() else {
+            self.status_message = Some("Already at the filesystem root".into());
+            return;
+        };
+        let old_dir = self.current_dir.clone();
+        if self.change_dir(parent.to_path_buf()) {
+            self.select_path(&old_dir);
+        }
+    }
+
+    fn set_quickmark(&mut self, mark: char) {
+        self.state.quickmarks.insert(mark, self.current_dir.clone());
+        self.status_message = Some(match self.state.save() {
+            Ok(()) => format!("Quickmark '{}' set to {}", mark, self.current_dir.display()),
+            Err(e) => format!("Quickmark '{}' set, but saving failed: {}", mark, e),
+        });
+    }
+
+    fn jump_to_quickmark(&mut self, mark: char) {
+        let Some(dir) = self.state.quickmarks.get(&mark).cloned() else {
+            self.status_message = Some(format!("Quickmark '{}' is not set", mark));
+            return;
+        };
+        if !dir.is_dir() {
+            self.status_message = Some(format!(
+                "Quickmark '{}' points to a missing directory: {}",
+                mark,
+                dir.display()
+            ));
+            return;
+        }
+        self.change_dir(dir);
+    }
+
+    /// Offers the quickmarked directories to move the selection into, or
+    /// to copy it into if `copy` is set.
+    fn pick_file_away_dir(&mut self, copy: bool) {
+        let Some(path) = self.entries.get(self.selected).map(|e| e.path()) else {
+            return;
+        };
+        let mut marks: Vec<_> = self.state.quickmarks.iter().collect();
+        if marks.is_empty() {
+            self.status_message = Some("No quickmarks set (m<letter> sets one)".into());
+            return;
+        }
+        marks.sort();
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        self.picker = Some(Picker {
+            title: format!("{} {} to", if copy { "Copy" } else { "Move" }, name),
+            items: marks
+                .iter()
+                .map(|(mark, dir)| format!("{}  {}", mark, dir.display()))
+                .collect(),
+            selected: 0,
+            purpose: PickerPurpose::FileAway {
+                copy,
+                dirs: marks.into_iter().map(|(_, dir)| dir.clone()).collect(),
+                path,
+            },
+        });
+    }
+
+    fn file_away(&mut self, path: &Path, dir: &Path, copy: bool) {
+        let name = path.file_name().unwrap_or_default();
+        if !dir.is_dir() {
+            self.status_message = Some(format!("{} is not a directory", dir.display()));
+            return;
+        }
+        if !copy && path.parent() == Some(dir) {
+            self.status_message = Some(format!(
+                "{} is already in {}",
+                name.to_string_lossy(),
+                dir.display()
+            ));
+            return;
+        }
+        if path.is_dir() && dir.starts_with(path) {
+            self.status_message = Some("Cannot put a directory inside itself".into());
+            return;
+        }
+        let target = fileops::unique_destination(dir, Path::new(name));
+        let result = if copy {
+            fileops::copy_recursive(path, &target)
+        } else {
+            fileops::move_path(path, &target)
+        };
+        let verb = if copy { "Copied" } else { "Moved" };
+        self.status_message = Some(match result {
+            Ok(()) if target.file_name() == Some(name) => {
+                format!("{} {} to {}", verb, name.to_string_lossy(), dir.display())
+            }
+            Ok(()) => format!(
+                "{} {} to {} as {}",
+                verb,
+                name.to_string_lossy(),
+                dir.display(),
+                target.file_name().unwrap_or_default().to_string_lossy()
+            ),
+            Err(e) => format!(
+                "{} failed for {}: {}",
+                if copy { "Copy" } else { "Move" },
+                name.to_string_lossy(),
+                e
+            ),
+        });
+        self.reread_entries();
+    }
+
+    /// Opens a shell next to lazycat in the selected directory, or in the
+    /// current one when a file is selected.
+    fn open_split_shell(&mut self) {
+        let dir = match self.entries.get(self.selected).map(|e| e.path()) {
+            Some(path) if path.is_dir() => path,
+            _ => self.current_dir.clone(),
+        };
+        self.status_message = Some(
+            match split::open_shell(&dir, self.config.split_command.as_deref()) {
+                Ok(what) => format!("Opened a shell in {} via {}", dir.display(), what),
+                Err(e) => format!("Cannot open a shell: {}", e),
+            },
+        );
+    }
+
+    fn emit_selection(&mut self) {
+        let Some(target) = &self.config.emit else {
+            self.status_message = Some("No emit target configured ([emit] in config)".into());
+            return;
+        };
+        let Some(path) = self.entries.get(self.selected).map(|e| e.path()) else {
+            return;
+        };
+        // A command may take as long as it likes, e.g. an editor that waits
+        // for its window to close, so it runs on its own thread.
+        let target = target.clone();
+        let tx = self.emit_tx.clone();
+        self.status_message = Some(format!("Sending {}...", path.display()));
+        thread::spawn(move || {
+            let _ = tx.send(match emit::emit(&target, &path) {
+                Ok(to) => format!("Sent {} to {}", path.display(), to),
+                Err(e) => format!("Emit failed: {}", e),
+            });
+        });
+    }
+
+    /// Reports emits that have finished.
+    fn receive_emits(&mut self) {
+        while let Ok(message) = self.emit_rx.try_recv() {
+            self.status_message = Some(message);
+        }
+    }
+
+    /// Reports the selected file's apparent size next to the space it
+    /// really occupies, flagging sparse (or compressed) files.
+    fn show_allocated_size(&mut self) {
+        let Some(entry) = self.entries.get(self.selected) else {
+            return;
+        };
+        let metadata = match entry.metadata() {
+            Ok(metadata) => metadata,
+            Err(e) => {
+                self.status_message = Some(format!("Cannot read metadata: {}", e));
+                return;
+            }
+        };
+        let apparent = metadata.len();
+        let Some(allocated) = format::allocated_size(&metadata) else {
+            self.status_message = Some(format!(
+                "{}, allocated size unavailable on this platform",
+                format::human_size(apparent)
+            ));
+            return;
+        };
+        let mut message = format!(
+            "Apparent {}, allocated {}",
+            format::human_size(apparent),
+            format::human_size(allocated)
+        );
+        // Below half is well past what block rounding could explain.
+        if allocated < apparent / 2 {
+            message.push_str(&format!(
+                " (sparse or compressed, {}% on disk)",
+                allocated * 100 / apparent
+            ));
+        }
+        self.status_message = Some(message);
+    }
+
+    fn start_checksum(&mut self) {
+        let Some(path) = self.entries.get(self.selected).map(|e| e.path()) else {
+            return;
+        };
+        if path.is_dir() {
+            self.status_message = Some("Cannot checksum a directory".into());
+            return;
+        }
+        // Replacing a running job drops its receiver, which stops its worker.
+        match checksum::Job::start(path, self.config.checksum_algorithm) {
+            Ok(job) => self.checksum_job = Some(job),
+            Err(e) => self.status_message = Some(format!("Cannot checksum: {}", e)),
+        }
+    }
+
+    /// Turns progress from the running checksum job into status messages.
+    fn receive_checksum(&mut self) {
+        let Some(job) = &self.checksum_job else {
+            return;
+        };
+        let name = job
+            .path
+            .file_name()
+            .map_or_else(String::new, |n| n.to_string_lossy().to_string());
+        let mut finished = false;
+        while let Ok(progress) = job.rx.try_recv() {
+            self.status_message = Some(match progress {
+                checksum::Progress::Hashed(hashed) => format!(
+                    "{} of {}: {}%",
+                    job.algorithm.name(),
+                    name,
+                    hashed * 100 / job.total.max(1)
+                ),
+                checksum::Progress::Done(Ok(digest)) => {
+                    finished = true;
+                    format!("{} {}  {}", job.algorithm.name(), digest, name)
+                }
+                checksum::Progress::Done(Err(e)) => {
+                    finished = true;
+                    format!("{} of {} failed: {}", job.algorithm.name(), name, e)
+                }
+            });
+        }
+        if finished {
+            self.checksum_job = None;
+        }
+    }
+
+    fn copy_to_clipboard(&mut self, text: &str, what: &str) {
+        self.status_message = Some(match clipboard::copy(text) {
+            Ok(()) => format!("Copied {}", what),
+            Err(e) => format!("Cannot copy to the clipboard: {}", e),
+        });
+    }
+
+    /// Copies the selected entry's absolute path, or with `name_only` just
+    /// its file name.
+    fn copy_selected_path(&mut self, name_only: bool) {
+        let Some(entry) = self.entries.get(self.selected) else {
+            return;
+        };
+        let (text, what) = if name_only {
+            (entry.file_name().to_string_lossy().into_owned(), "name")
+        } else {
+            // `current_dir` is absolute, and so are the entries read from it.
+            (entry.path().to_string_lossy().into_owned(), "path")
+        };
+        self.copy_to_clipboard(&text, &format!("{} {}", what, text));
+    }
+
+    /// Copies the selected file's text. Files over `COPY_CONTENTS_LIMIT`
+    /// ask before copying only their first part.
+    fn copy_file_contents(&mut self) {
+        let Some(path) = self.entries.get(self.selected).map(|e| e.path()) else {
+            return;
+        };
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        if path.is_dir() {
+            self.status_message = Some(format!("{} is a directory", name));
+            return;
+        }
+        let mut bytes = Vec::new();
+        let read = File::open(&path).and_then(|f| {
+            f.take(COPY_CONTENTS_LIMIT as u64 + 1)
+                .read_to_end(&mut bytes)
+        });
+        if let Err(e) = read {
+            self.status_message = Some(format!("Cannot read {}: {}", name, e));
+            return;
+        }
+        let truncated = bytes.len() > COPY_CONTENTS_LIMIT;
+        bytes.truncate(COPY_CONTENTS_LIMIT);
+        let text = match String::from_utf8(bytes) {
+            Ok(text) if !text.contains('\0') => text,
+            // The cut may split a multi-byte character; drop the fragment.
+            Err(e) if truncated && e.utf8_error().error_len().is_none() => {
+                let valid = e.utf8_error().valid_up_to();
+                let mut bytes = e.into_bytes();
+                bytes.truncate(valid);
+                String::from_utf8(bytes).unwrap_or_default()
+            }
+            _ => {
+                self.status_message = Some(format!("Not copying {}: binary file", name));
+                return;
+            }
+        };
+        let what = format!("{} ({})", name, human_size(text.len() as u64));
+        if truncated {
+            self.confirm = Some(Confirm {
+                prompt: format!(
+                    "{} is over {}; copy only the first part? (y/n)",
+                    name,
+                    human_size(COPY_CONTENTS_LIMIT as u64)
+                ),
+                action: ConfirmAction::CopyText {
+                    text,
+                    what: format!("the first part of {}", what),
+                },
+            });
+        } else {
+            self.copy_to_clipboard(&text, &what);
+        }
+    }
+
+    fn run_confirmed(&mut self, action: ConfirmAction) {
+        match action {
+            ConfirmAction::CopyText { text, what } => self.copy_to_clipboard(&text, &what),
+            ConfirmAction::Delete(paths) => self.delete(&paths),
+            ConfirmAction::SetReadOnly { path, read_only } => {
+                let name = path.file_name().unwrap_or_default().to_string_lossy();
+                self.status_message = Some(match set_read_only(&path, read_only) {
+                    Ok(()) if read_only => format!("{} is now read-only", name),
+                    Ok(()) => format!("{} is now writable", name),
+                    Err(e) => format!("Cannot change {}: {}", name, e),
+                });
+            }
+        }
+    }
+
+    fn start_rename(&mut self) {
+        let Some(entry) = self.entries.get(self.selected) else {
+            return;
+        };
+        self.input = Some(Input {
+            prompt: "Rename to: ".into(),
+            text: entry.file_name().to_string_lossy().to_string(),
+            error: None,
+            purpose: InputPurpose::Rename { from: entry.path() },
+        });
+    }
+
+    fn start_create(&mut self, directory: bool) {
+        let (prompt, purpose) = if directory {
+            ("New directory: ", InputPurpose::NewDirectory)
+        } else {
+            (
+                "New file (end in / for a directory): ",
+                InputPurpose::NewFile,
+            )
+        };
+        self.input = Some(Input {
+            prompt: prompt.into(),
+            text: String::new(),
+            error: None,
+            purpose,
+        });
+    }
+
+    /// Creates `name` under the current directory, with any parents it
+    /// names, and selects the entry it ends up under.
+    fn create(&mut self, name: &str, directory: bool) -> Result<(), String> {
+        let relative = Path::new(name);
+        if !relative
+            .components()
+            .all(|c| matches!(c, std::path::Component::Normal(_)))
+        {
+            return Err("name must stay inside this directory".into());
+        }
+        let target = self.current_dir.join(relative);
+        if fs::symlink_metadata(&target).is_ok() {
+            return Err(format!("{} already exists", name));
+        }
+        let created = if directory {
+            fs::create_dir_all(&target)
+        } else {
+            target
+                .parent()
+                .map_or(Ok(()), fs::create_dir_all)
+                .and_then(|()| fs::File::create_new(&target).map(drop))
+        };
+        if let Err(e) = created {
+            return Err(e.to_string());
+        }
+        self.status_message = Some(format!("Created {}", name));
+        self.reread_entries();
+        if let Some(top) = relative.components().next() {
+            self.select_path(&self.current_dir.join(top));
+        }
+        Ok(())
+    }
+
+    /// Marks the selected entry, or unmarks it, and moves on to the next.
+    fn toggle_mark(&mut self) {
+        let Some(path) = self.entries.get(self.selected).map(|e| e.path()) else {
+            return;
+        };
+        if !self.marked.remove(&path) {
+            self.marked.insert(path);
+        }
+        self.move_down();
+    }
+
+    /// The marked entries, or the selected one if nothing is marked, in
+    /// order. Marks inside a marked directory are left out, as that takes
+    /// them along.
+    fn marked_or_selected(&self) -> Vec<PathBuf> {
+        let mut paths: Vec<PathBuf> = if self.marked.is_empty() {
+            self.entries
+                .get(self.selected)
+                .map(|e| e.path())
+                .into_iter()
+                .collect()
+        } else {
+            self.marked.iter().cloned().collect()
+        };
+        // Sorting puts everything inside a directory right after it.
+        paths.sort();
+        paths.dedup_by(|path, kept| path.starts_with(kept));
+        paths
+    }
+
+    /// Asks whether to delete the marked entries, or the selected one if
+    /// nothing is marked. Marks survive changing directory, so the prompt
+    /// names them from here and says how many aren't in this listing.
+    fn confirm_delete(&mut self) {
+        let paths = self.marked_or_selected();
+        if paths.is_empty() {
+            return;
+        }
+        let names: Vec<_> = paths
+            .iter()
+            .take(3)
+            .map(|p| {
+                let shown = p.strip_prefix(&self.current_dir).unwrap_or(p);
+                shown.display().to_string()
+            })
+            .collect();
+        let mut prompt = match paths.len() {
+            1 => format!("Delete {}", names[0]),
+            n => format!("Delete {} entries: {}", n, names.join(", ")),
+        };
+        if paths.len() > names.len() {
+            prompt.push_str(&format!(" and {} more", paths.len() - names.len()));
+        }
+        let outside = paths
+            .iter()
+            .filter(|p| p.parent() != Some(self.current_dir.as_path()))
+            .count();
+        if outside > 0 {
+            prompt.push_str(&format!(" ({} outside this directory)", outside));
+        }
+        prompt.push_str("? (y/n)");
+        self.confirm = Some(Confirm {
+            prompt,
+            action: ConfirmAction::Delete(paths),
+        });
+    }
+
+    /// Deletes `paths`, directories with everything in them. A link is
+    /// removed itself, never what it points to.
+    fn delete(&mut self, paths: &[PathBuf]) {
+        let mut deleted = 0;
+        let mut failure = None;
+        for path in paths {
+            let result = match fs::symlink_metadata(path) {
+                Ok(metadata) if metadata.is_dir() => fs::remove_dir_all(path),
+                Ok(_) => fs::remove_file(path),
+                Err(e) => Err(e),
+            };
+            match result {
+                Ok(()) => {
+                    deleted += 1;
+                    self.marked.retain(|marked| !marked.starts_with(path));
+                }
+                Err(e) => {
+                    let name = path.file_name().unwrap_or_default().to_string_lossy();
+                    failure.get_or_insert_with(|| format!("{}: {}", name, e));
+                }
+            }
+        }
+        self.status_message = Some(match failure {
+            None if deleted == 1 => "Deleted 1 entry".to_string(),
+            None => format!("Deleted {} entries", deleted),
+            Some(e) => format!(
+                "Deleted {} of {} entries; cannot delete {}",
+                deleted,
+                paths.len(),
+                e
+            ),
+        });
+        self.reread_entries();
+    }
+
+    /// Puts the marked entries, or the selected one, in the register for
+    /// `paste`, unmarking them.
+    fn fill_register(&mut self, cut: bool) {
+        let paths = self.marked_or_selected();
+        if paths.is_empty() {
+            return;
+        }
+        self.status_message = Some(format!(
+            "{} {} to paste",
+            if cut { "Cut" } else { "Copied" },
+            match paths.len() {
+                1 => paths[0]
+                    .file_name()
+                    .unwrap_or_default()
+                    .to_string_lossy()
+                    .to_string(),
+                n => format!("{} entries", n),
+            }
+        ));
+        self.marked.clear();
+        self.register = Some(Register { paths, cut });
+    }
+
+    /// Copies or moves what the register holds into the current directory.
+    /// A cut register is emptied by it; a copied one can be pasted again.
+    fn paste(&mut self) {
+        let Some(register) = &self.register else {
+            self.status_message = Some("Nothing to paste".into());
+            return;
+        };
+        self.paste = Some(Paste {
+            queue: register.paths.iter().cloned().collect(),
+            cut: register.cut,
+            pasted: 0,
+            skipped: 0,
+            failure: None,
+            prompt: None,
+        });
+        if register.cut {
+            self.register = None;
+        }
+        self.resume_paste(None);
+    }
+
+    /// Pastes entries until one collides, and asks about it, or until all
+    /// are done. `choice` settles the collision being asked about.
+    fn resume_paste(&mut self, mut choice: Option<Collision>) {
+        let Some(mut paste) = self.paste.take() else {
+            return;
+        };
+        paste.prompt = None;
+        while let Some(path) = paste.queue.front().cloned() {
+            let name = path.file_name().unwrap_or_default();
+            let mut target = self.current_dir.join(name);
+            let mut overwrite = false;
+            let failed = |paste: &mut Paste, e: String| {
+                paste
+                    .failure
+                    .get_or_insert_with(|| format!("{}: {}", name.to_string_lossy(), e));
+                paste.queue.pop_front();
+            };
+            if fs::symlink_metadata(&path).is_err() {
+                failed(&mut paste, "no longer exists".into());
+                continue;
+            }
+            if path.is_dir() && self.current_dir.starts_with(&path) {
+                failed(&mut paste, "cannot put a directory inside itself".into());
+                continue;
+            }
+            if target == path {
+                // Cut from here: nothing to do. Copied from here: a duplicate.
+                if paste.cut {
+                    paste.skipped += 1;
+                    paste.queue.pop_front();
+                    continue;
+                }
+                target = fileops::numbered_destination(&self.current_dir, &path);
+            } else if fs::symlink_metadata(&target).is_ok() {
+                match choice.take() {
+                    None => {
+                        paste.prompt = Some(format!(
+                            "{} exists: (o)verwrite, (s)kip, (r)ename, Esc stops",
+                            name.to_string_lossy()
+                        ));
+                        self.paste = Some(paste);
+                        return;
+                    }
+                    Some(Collision::Skip) => {
+                        paste.skipped += 1;
+                        paste.queue.pop_front();
+                        continue;
+                    }
+                    Some(Collision::Rename) => {
+                        target = fileops::numbered_destination(&self.current_dir, &path);
+                    }
+                    Some(Collision::Overwrite) => {
+                        if path.starts_with(&target) {
+                            failed(&mut paste, "cannot overwrite what contains it".into());
+                            continue;
+                        }
+                        overwrite = true;
+                    }
+                }
+            }
+            let result = if overwrite {
+                fileops::replace(&path, &target, paste.cut)
+            } else {
+                fileops::transfer(&path, &target, paste.cut)
+            };
+            match result {
+                Ok(()) => {
+                    paste.pasted += 1;
+                    paste.queue.pop_front();
+                }
+                Err(e) => failed(&mut paste, e.to_string()),
+            }
+        }
+        self.finish_paste(paste);
+    }
+
+    fn finish_paste(&mut self, paste: Paste) {
+        let verb = if paste.cut { "Moved" } else { "Copied" };
+        let mut message = match paste.pasted {
+            1 => format!("{} 1 entry", verb),
+            n => format!("{} {} entries", verb, n),
+        };
+        if paste.skipped > 0 {
+            message.push_str(&format!(", skipped {}", paste.skipped));
+        }
+        if !paste.queue.is_empty() {
+            message.push_str(&format!(", left {}", paste.queue.len()));
+        }
+        // What a stopped cut didn't get to can still be pasted.
+        if paste.cut && !paste.queue.is_empty() {
+            self.register = Some(Register {
+                paths: paste.queue.iter().cloned().collect(),
+                cut: true,
+            });
+        }
+        if let Some(e) = paste.failure {
+            message.push_str(&format!("; cannot paste {}", e));
+        }
+        self.status_message = Some(message);
+        self.reread_entries();
+    }
+
+    /// Asks whether to flip the selected file between read-only and
+    /// writable.
+    fn toggle_read_only(&mut self) {
+        let Some(entry) = self.entries.get(self.selected) else {
+            return;
+        };
+        let path = entry.path();
+        let name = entry.file_name().to_string_lossy().to_string();
+        let read_only = match entry.metadata() {
+            Ok(metadata) => !metadata.permissions().readonly(),
+            Err(e) => {
+                self.status_message = Some(format!("Cannot read {}: {}", name, e));
+                return;
+            }
+        };
+        let prompt = if read_only {
+            format!("Make {} read-only? (y/n)", name)
+        } else {
+            format!("Make {} writable? (y/n)", name)
+        };
+        self.confirm = Some(Confirm {
+            prompt,
+            action: ConfirmAction::SetReadOnly { path, read_only },
+        });
+    }
+
+    /// Starts cycling through the recent directories, or steps to the next
+    /// older one if already cycling. The jump happens once `-` has not been
+    /// pressed for `RECENT_CYCLE_COMMIT`.
+    fn cycle_recent_dirs(&mut self) {
+        // Entry 0 is the current directory itself.
+        let len = self.state.recent_dirs.len();
+        if len < 2 {
+            self.status_message = Some("No other recent directories".into());
+            return;
+        }
+        let index = match &self.recent_cycle {
+            Some(cycle) => cycle.index % (len - 1) + 1,
+            None => 1,
+        };
+        self.recent_cycle = Some(RecentCycle {
+            index,
+            last_press: Instant::now(),
+        });
+    }
+
+    /// Jumps to the directory picked by a recent-directory cycle once it has
+    /// settled. `force` commits right away.
+    fn settle_recent_cycle(&mut self, force: bool) {
+        let Some(cycle) = &self.recent_cycle else {
+            return;
+        };
+        if !force && cycle.last_press.elapsed() < RECENT_CYCLE_COMMIT {
+            return;
+        }
+        let index = cycle.index;
+        self.recent_cycle = None;
+        let Some(dir) = self.state.recent_dirs.get(index).cloned() else {
+            return;
+        };
+        if !dir.is_dir() {
+            self.state.recent_dirs.remove(index);
+            self.status_message = Some(format!("{} no longer exists", dir.display()));
+            return;
+        }
+        self.change_dir(dir);
+    }
+
+    fn scroll_preview_down(&mut self, amount: u16) {
+        let max_scroll = (self.preview_lines.len() as u16).saturating_sub(1);
+        self.preview_scroll = (self.preview_scroll + amount).min(max_scroll);
+    }
+
+    fn scroll_preview_up(&mut self, amount: u16) {
+        self.preview_scroll = self.preview_scroll.saturating_sub(amount);
+    }
+
+    /// Scrolls the preview right by `amount` columns, no further than needed
+    /// to bring the end of the longest line into view.
+    fn scroll_preview_right(&mut self, amount: u16) {
+        if self.wrap_enabled {
+            return;
+        }
+        let longest = self
+            .preview_lines
+            .iter()
+            .map(Line::width)
+            .max()
+            .unwrap_or(0);
+        let width = usize::from(self.preview_area.width.saturating_sub(2));
+        let max_scroll = longest.saturating_sub(width).min(usize::from(u16::MAX)) as u16;
+        self.preview_hscroll = (self.preview_hscroll + amount).min(max_scroll);
+    }
+
+    fn scroll_preview_left(&mut self, amount: u16) {
+        self.preview_hscroll = self.preview_hscroll.saturating_sub(amount);
+    }
+
+    /// Right or `l`: opens the selected directory, or with the preview
+    /// focused, scrolls it right.
+    fn step_right(&mut self) {
+        if self.preview_focused {
+            self.scroll_preview_right(self.preview_area.width / 2);
+        } else {
+            self.enter_directory();
+        }
+    }
+
+    /// Left or `h`: goes to the parent directory, or with the preview
+    /// focused, scrolls it left.
+    fn step_left(&mut self) {
+        if self.preview_focused {
+            self.scroll_preview_left(self.preview_area.width / 2);
+        } else {
+            self.go_parent();
+        }
+    }
+
+    fn toggle_wrap(&mut self) {
+        self.wrap_enabled = !self.wrap_enabled;
+        self.preview_hscroll = 0;
+        self.show_search_match();
+        self.status_message = Some(
+            if self.wrap_enabled {
+                "Wrapping long lines"
+            } else {
+                "Not wrapping long lines"
+            }
+            .into(),
+        );
+    }
+
+    /// Clicking an entry selects it and double clicking opens it; the wheel
+    /// moves the selection or scrolls the preview, whichever it is over.
+    fn handle_mouse(&mut self, mouse: MouseEvent) {
+        let in_list = self.list_area.contains((mouse.column, mouse.row).into());
+        let in_preview = self.preview_area.contains((mouse.column, mouse.row).into());
+        match mouse.kind {
+            MouseEventKind::Down(MouseButton::Left) if in_list => {
+                let top = self.list_area.y + 1;
+                if mouse.row < top || mouse.row >= self.list_area.bottom().saturating_sub(1) {
+                    return;
+                }
+                let index = self.list_offset + usize::from(mouse.row - top) / self.entry_height();
+                if index >= self.entries.len() {
+                    return;
+                }
+                let double = self
+                    .last_click
+                    .is_some_and(|(last, at)| last == index && at.elapsed() < DOUBLE_CLICK);
+                if index != self.selected {
+                    self.selected = index;
+                    self.update_preview();
+                }
+                if double {
+                    self.last_click = None;
+                    self.enter_directory();
+                } else {
+                    self.last_click = Some((index, Instant::now()));
+                }
+            }
+            MouseEventKind::ScrollDown if in_list => {
+                for _ in 0..WHEEL_LINES {
+                    self.move_down();
+                }
+            }
+            MouseEventKind::ScrollUp if in_list => {
+                for _ in 0..WHEEL_LINES {
+                    self.move_up();
+                }
+            }
+            MouseEventKind::ScrollDown if in_preview => self.scroll_preview_down(WHEEL_LINES),
+            MouseEventKind::ScrollUp if in_preview => self.scroll_preview_up(WHEEL_LINES),
+            _ => {}
+        }
+    }
+
+    fn start_preview_search(&mut self) {
+        self.input = Some(Input {
+            prompt: "Search preview: ".into(),
+            text: self
+                .preview_search
+                .as_ref()
+                .map(|search| search.query.clone())
+                .unwrap_or_default(),
+            error: None,
+            purpose: InputPurpose::PreviewSearch,
+        });
+    }
+
+    /// Finds `query` in the preview and jumps to the first match at or
+    /// below the top of the pane.
+    fn search_preview(&mut self, query: String) -> Result<(), String> {
+        let matches = search::find(&self.preview_lines, &query);
+        if matches.is_empty() {
+            return Err(format!("{} not found", query));
+        }
+        let top = usize::from(self.preview_scroll);
+        let current = matches.iter().position(|m| m.line >= top).unwrap_or(0);
+        self.preview_search = Some(PreviewSearch {
+            query,
+            matches,
+            current,
+        });
+        self.show_search_match();
+        Ok(())
+    }
+
+    /// Moves to the next match, or with `backwards` the previous one,
+    /// wrapping around at either end.
+    fn step_preview_search(&mut self, backwards: bool) {
+        let Some(search) = &mut self.preview_search else {
+            return;
+        };
+        let count = search.matches.len();
+        if count == 0 {
+            self.status_message = Some(format!("{} not found", search.query));
+            return;
+        }
+        search.current = if backwards {
+            (search.current + count - 1) % count
+        } else {
+            (search.current + 1) % count
+        };
+        self.show_search_match();
+    }
+
+    /// Scrolls the current match into view if it is off screen.
+    fn show_search_match(&mut self) {
+        let Some(m) = self
+            .preview_search
+            .as_ref()
+            .and_then(|search| search.matches.get(search.current))
+        else {
+            return;
+        };
+        let top = usize::from(self.preview_scroll);
+        let height = usize::from(self.preview_height.max(1));
+        if m.line < top || m.line >= top + height {
+            self.preview_scroll = m.line.saturating_sub(height / 3) as u16;
+        }
+        if !self.wrap_enabled {
+            let left = usize::from(self.preview_hscroll);
+            let width = usize::from(self.preview_area.width.saturating_sub(2)).max(1);
+            if m.columns.start < left || m.columns.end > left + width {
+                self.preview_hscroll = m.columns.start.saturating_sub(width / 3) as u16;
+            }
+        }
+    }
+
+    /// Scrolls `list_offset` just enough to keep the selection inside a pane
+    /// of `height` rows and returns the range of entries worth rendering.
+    fn visible_range(&mut self, height: usize) -> Range<usize> {
+        let height = height.max(1);
+        if self.center_selection {
+            self.list_offset = self.selected.saturating_sub(height / 2);
+        } else if self.selected < self.list_offset {
+            self.list_offset = self.selected;
+        } else if self.selected >= self.list_offset + height {
+            self.list_offset = self.selected + 1 - height;
+        }
+        self.list_offset = self
+            .list_offset
+            .min(self.entries.len().saturating_sub(height));
+        let end = (self.list_offset + height + LIST_RENDER_BUFFER).min(self.entries.len());
+        self.list_offset..end
+    }
+
+    fn toggle_hidden(&mut self) {
+        self.show_hidden = !self.show_hidden;
+        let selected_path = self.entries.get(self.selected).map(|e| e.path());
+        self.reread_entries();
+        if let Some(path) = selected_path {
+            self.select_path(&path);
+        }
+    }
+
+    fn toggle_git_ignored(&mut self) {
+        self.hide_ignored = !self.hide_ignored;
+        let selected_path = self.entries.get(self.selected).map(|e| e.path());
+        self.reread_entries();
+        if let Some(path) = selected_path {
+            self.select_path(&path);
+        }
+    }
+
+    /// Applies `kind` as the type filter, or clears it if it is already the
+    /// active one. The selected entry stays selected when it survives.
+    fn toggle_type_filter(&mut self, kind: Option<EntryKind>) {
+        self.type_filter = if self.type_filter == kind { None } else { kind };
+        let selected_path = self.entries.get(self.selected).map(|e| e.path());
+        self.reread_entries();
+        if let Some(path) = selected_path {
+            self.select_path(&path);
+        }
+    }
+
+    /// Selects the entry at `path` if it is listed, returning whether it was.
+    /// While the listing is still loading, an entry not read yet is
+    /// selected when it arrives.
+    fn select_path(&mut self, path: &Path) -> bool {
+        match self.entries.iter().position(|e| e.path() == path) {
+            Some(idx) => {
+                self.selected = idx;
+                self.update_preview();
+                true
+            }
+            None => {
+                if self.listing_loading {
+                    self.pending_selection = Some(path.to_path_buf());
+                }
+                false
+            }
+        }
+    }
+
+    /// Lists the other names the selected file goes by within the current
+    /// directory tree.
+    fn pick_hard_links(&mut self) {
+        let Some(path) = self.entries.get(self.selected).map(|e| e.path()) else {
+            return;
+        };
+        let name = path
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .to_string();
+        if path.is_dir() {
+            self.status_message = Some(format!("{} is a directory", name));
+            return;
+        }
+        let scan = match hardlinks::find_links(&path, &self.current_dir, HARDLINK_SCAN_LIMIT) {
+            Ok(scan) => scan,
+            Err(e) => {
+                self.status_message = Some(format!("Cannot look for links: {}", e));
+                return;
+            }
+        };
+        let partial = if scan.complete {
+            ""
+        } else {
+            " (search stopped early)"
+        };
+        if scan.paths.is_empty() {
+            self.status_message = Some(format!(
+                "No other links to {} under this directory{}",
+                name, partial
+            ));
+            return;
+        }
+        self.picker = Some(Picker {
+            title: format!("Links to {}{}", name, partial),
+            items: scan
+                .paths
+                .iter()
+                .map(|p| {
+                    p.strip_prefix(&self.current_dir)
+                        .unwrap_or(p)
+                        .display()
+                        .to_string()
+                })
+                .collect(),
+            selected: 0,
+            purpose: PickerPurpose::HardLinks(scan.paths),
+        });
+    }
+
+    /// Opens the picker over the files in the templates directory.
+    fn pick_template(&mut self) {
+        let Some(dir) = self.config.templates_dir.clone() else {
+            self.status_message = Some("No templates directory configured".into());
+            return;
+        };
+        match templates::list(&dir) {
+            Ok(list) if list.is_empty() => {
+                self.status_message = Some(format!("No templates in {}", dir.display()));
+            }
+            Ok(list) => {
+                self.picker = Some(Picker {
+                    title: "New from template".into(),
+                    items: list
+                        .iter()
+                        .map(|p| {
+                            p.file_name()
+                                .unwrap_or_default()
+                                .to_string_lossy()
+                                .to_string()
+                        })
+                        .collect(),
+                    selected: 0,
+                    purpose: PickerPurpose::Template(list),
+                });
+            }
+            Err(e) => {
+                self.status_message = Some(format!("Cannot read {}: {}", dir.display(), e));
+            }
+        }
+    }
+
+    fn handle_picker_key(&mut self, code: KeyCode) {
+        let Some(picker) = &mut self.picker else {
+            return;
+        };
+        match code {
+            KeyCode::Char('j') | KeyCode::Down => {
+                picker.selected = (picker.selected + 1).min(picker.items.len().saturating_sub(1));
+            }
+            KeyCode::Char('k') | KeyCode::Up => picker.selected = picker.selected.saturating_sub(1),
+            KeyCode::Enter => {
+                let Some(picker) = self.picker.take() else {
+                    return;
+                };
+                match picker.purpose {
+                    PickerPurpose::FileAway { path, copy, dirs } => {
+                        self.file_away(&path, &dirs[picker.selected], copy);
+                    }
+                    PickerPurpose::HardLinks(paths) => {
+                        let path = &paths[picker.selected];
+                        if let Some(dir) = path.parent()
+                            && self.change_dir(dir.to_path_buf())
+                        {
+                            self.select_path(path);
+                        }
+                    }
+                    PickerPurpose::Template(list) => {
+                        let template = list[picker.selected].clone();
+                        self.input = Some(Input {
+                            prompt: "New file name: ".into(),
+                            text: template
+                                .file_name()
+                                .unwrap_or_default()
+                                .to_string_lossy()
+                                .to_string(),
+                            error: None,
+                            purpose: InputPurpose::TemplateFileName { template },
+                        });
+                    }
+                }
+            }
+            KeyCode::Esc | KeyCode::Char('q') => self.picker = None,
+            _ => {}
+        }
+    }
+
+    fn handle_input_key(&mut self, code: KeyCode) {
+        let Some(input) = &mut self.input else {
+            return;
+        };
+        match code {
+            KeyCode::Char(c) => input.text.push(c),
+            KeyCode::Backspace => {
+                input.text.pop();
+            }
+            KeyCode::Esc => self.input = None,
+            KeyCode::Enter => {
+                if let Err(e) = self.submit_input() {
+                    if let Some(input) = &mut self.input {
+                        input.error = Some(e);
+                    }
+                } else {
+                    self.input = None;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Acts on the typed text. The inner error is a problem with what was
+    /// typed, shown in the still-open prompt.
+    fn submit_input(&mut self) -> Result<(), String> {
+        let Some(input) = &self.input else {
+            return Ok(());
+        };
+        if let InputPurpose::PreviewSearch = input.purpose {
+            if input.text.is_empty() {
+                return Err("nothing to search for".into());
+            }
+            return self.search_preview(input.text.clone());
+        }
+        let name = input.text.trim().to_string();
+        if name.is_empty() {
+            return Err("name cannot be empty".into());
+        }
+        match input.purpose {
+            InputPurpose::NewFile => {
+                return match name.strip_suffix('/') {
+                    Some(dir) => self.create(dir, true),
+                    None => self.create(&name, false),
+                };
+            }
+            InputPurpose::NewDirectory => return self.create(&name, true),
+            _ => {}
+        }
+        if name.contains(['/', std::path::MAIN_SEPARATOR]) {
+            return Err("name cannot contain a path separator".into());
+        }
+        match &input.purpose {
+            InputPurpose::PreviewSearch | InputPurpose::NewFile | InputPurpose::NewDirectory => {}
+            InputPurpose::Rename { from } => {
+                let from = from.clone();
+                if from.file_name() == Some(OsStr::new(&name)) {
+                    return Ok(());
+                }
+                let target = self.current_dir.join(&name);
+                if fs::symlink_metadata(&target).is_ok() && !fileops::same_entry(&from, &target) {
+                    return Err(format!("{} already exists", name));
+                }
+                if let Err(e) = fs::rename(&from, &target) {
+                    return Err(e.to_string());
+                }
+                if self.marked.remove(&from) {
+                    self.marked.insert(target.clone());
+                }
+                self.status_message = Some(format!(
+                    "Renamed {} to {}",
+                    from.file_name().unwrap_or_default().to_string_lossy(),
+                    name
+                ));
+                self.reread_entries();
+                self.select_path(&target);
+            }
+            InputPurpose::TemplateFileName { template } => {
+                let target = self.current_dir.join(&name);
+                if target.exists() {
+                    return Err(format!("{} already exists", name));
+                }
+                if let Err(e) = templates::instantiate(template, &target) {
+                    return Err(e.to_string());
+                }
+                self.status_message = Some(format!("Created {}", name));
+                self.reread_entries();
+                self.select_path(&target);
+            }
+        }
+        Ok(())
+    }
+
+    fn cycle_extension_display(&mut self) {
+        self.extension_display = self.extension_display.next();
+        self.status_message = Some(format!("Extensions: {}", self.extension_display.name()));
+    }
+
+    /// The status line, rendered from the configured `status.format`.
+    fn status_line(&self) -> String {
+        let entry = self.entries.get(self.selected);
+        let metadata = entry.and_then(|e| e.metadata().ok());
+        self.config.status_format.render(|field| match field {
+            Field::Index => entry.map_or(0, |_| self.selected + 1).to_string(),
+            Field::Count => self.entries.len().to_string(),
+            Field::Path => entry
+                .map_or_else(|| self.current_dir.clone(), |e| e.path())
+                .display()
+                .to_string(),
+            Field::Branch => self.git_branch.clone().unwrap_or_default(),
+            Field::Size => match (&metadata, entry) {
+                (Some(m), Some(entry)) if m.is_dir() => self
+                    .dir_count(&entry.path())
+                    .map_or_else(|| "-".to_string(), |n| format!("{} entries", n)),
+                (Some(m), _) => human_size(m.len()),
+                _ => "-".to_string(),
+            },
+            Field::Mode => metadata
+                .as_ref()
+                .map_or_else(|| "-".to_string(), format::permissions),
+            Field::Modified => metadata
+                .as_ref()
+                .and_then(|m| m.modified().ok())
+                .map_or_else(|| "-".to_string(), format::local_time),
+            Field::Marked => match self.marked.len() {
+                0 => String::new(),
+                n => format!("{} marked", n),
+            },
+        })
+    }
+
+    /// Rereads the current directory after something changed in it. If that
+    /// fails the old listing stays up, with the error in the status bar.
+    fn reread_entries(&mut self) {
+        if let Err(e) = self.refresh_entries() {
+            self.status_message = Some(format!("Cannot reread directory: {}", e));
+        }
+    }
+
+    /// Reads the whole of a directory whose listing was cut short by
+    /// `list.max_entries`.
+    fn load_all_entries(&mut self) {
+        if self.entries_capped.is_none() {
+            self.status_message = Some("All entries are already loaded".into());
+            return;
+        }
+        self.uncapped_dir = Some(self.current_dir.clone());
+        self.reread_entries();
+    }
+
+    /// How many entries `dir` holds, or `None` if it can't be read. Counts
+    /// are cached until the directory's mtime changes.
+    fn dir_count(&self, dir: &Path) -> Option<usize> {
+        let modified = fs::metadata(dir).and_then(|m| m.modified()).ok()?;
+        let mut counts = self.dir_counts.borrow_mut();
+        if let Some((cached_at, count)) = counts.get(dir)
+            && *cached_at == modified
+        {
+            return *count;
+        }
+        let count = fs::read_dir(dir).ok().map(|entries| entries.count());
+        counts.insert(dir.to_path_buf(), (modified, count));
+        count
+    }
+
+    fn toggle_two_line_entries(&mut self) {
+        self.two_line_entries = !self.two_line_entries;
+    }
+
+    fn toggle_icons(&mut self) {
+        self.use_icons = !self.use_icons;
+        // A directory preview lists entries with them too.
+        self.update_preview();
+    }
+
+    /// Terminal rows each entry takes up in the list.
+    fn entry_height(&self) -> usize {
+        if self.two_line_entries {
+            2
+        } else {
+            1
+        }
+    }
+
+    fn entry_name_line(&self, entry: &DirEntry, is_dir: bool, width: usize) -> Line<'static> {
+        let name = entry.file_name().to_string_lossy().to_string();
+        let matched = self
+            .filter
+            .as_deref()
+            .and_then(|query| fuzzy::match_indices(query, &name))
+            .unwrap_or_default();
+        if is_dir {
+            let mut line = Line::from(match_spans(&name, 0, &matched, Style::default()));
+            line.push_span(Span::raw("/"));
+            if self.show_dir_counts
+                && let Some(count) = self.dir_count(&entry.path())
+            {
+                line.push_span(Span::styled(
+                    format!(" ({})", count),
+                    Style::default().fg(Color::DarkGray),
+                ));
+            }
+            return line;
+        }
+        let path = entry.path();
+        let (Some(stem), Some(ext)) = (path.file_stem(), path.extension()) else {
+            return Line::from(match_spans(&name, 0, &matched, Style::default()));
+        };
+        let stem = stem.to_string_lossy().to_string();
+        let ext = format!(".{}", ext.to_string_lossy());
+        let stem_spans = match_spans(&stem, 0, &matched, Style::default());
+        match self.extension_display {
+            ExtensionDisplay::Full => Line::from(match_spans(&name, 0, &matched, Style::default())),
+            ExtensionDisplay::Hidden => Line::from(stem_spans),
+            ExtensionDisplay::Column => {
+                let stem_width = Line::from(stem_spans.clone()).width();
+                let ext_spans = match_spans(
+                    &ext,
+                    stem.chars().count(),
+                    &matched,
+                    Style::default().fg(Color::DarkGray),
+                );
+                let ext_width = Line::from(ext_spans.clone()).width();
+                let gap = width.saturating_sub(stem_width + ext_width).max(1);
+                let mut spans = stem_spans;
+                spans.push(Span::raw(" ".repeat(gap)));
+                spans.extend(ext_spans);
+                Line::from(spans)
+            }
+        }
+    }
+
+    /// The dim size / modified time / permissions line of the two-line layout.
+    fn entry_metadata_line(entry: &DirEntry) -> Line<'static> {
+        let text = match entry.metadata() {
+            Ok(metadata) => {
+                let size = if metadata.is_dir() {
+                    "-".to_string()
+                } else {
+                    human_size(metadata.len())
+                };
+                let modified = metadata
+                    .modified()
+                    .map_or_else(|_| "-".to_string(), format::local_time);
+                format!(
+                    "  {}  {}  {}",
+                    size,
+                    modified,
+                    format::permissions(&metadata)
+                )
+            }
+            Err(_) => "  -".to_string(),
+        };
+        Line::from(Span::styled(text, Style::default().fg(Color::DarkGray)))
+    }
+
+    /// Where `modified` falls between the heatmap's recent and old colors.
+    /// Age is taken logarithmically so the last hour and the last day are
+    /// as easy to tell apart as the last week and the last month.
+    fn heat_color(&self, modified: SystemTime) -> Color {
+        let age = SystemTime::now()
+            .duration_since(modified)
+            .unwrap_or_default()
+            .as_secs_f64();
+        let old = (self.config.heatmap_days * 24 * 60 * 60) as f64;
+        let t = ((1.0 + age).ln() / (1.0 + old).ln()).clamp(0.0, 1.0);
+        let (r1, g1, b1) = self.config.heatmap_recent;
+        let (r2, g2, b2) = self.config.heatmap_old;
+        let mix = |a: u8, b: u8| (a as f64 + (b as f64 - a as f64) * t).round() as u8;
+        Color::Rgb(mix(r1, r2), mix(g1, g2), mix(b1, b2))
+    }
+
+    /// Builds the rows for `range`. `width` is the room left for a name once
+    /// borders and the highlight symbol are accounted for, and is what the
+    /// extension column is right-aligned against.
+    fn get_list_items(&self, range: Range<usize>, width: usize) -> Vec<ListItem<'_>> {
+        self.entries[range]
+            .iter()
+            .map(|entry| {
+                let is_dir = entry.path().is_dir();
+                // `DirEntry::metadata` describes a link itself, not its target.
+                let metadata = entry.metadata();
+                let is_symlink = metadata.as_ref().is_ok_and(|m| m.is_symlink());
+                let heat = metadata
+                    .as_ref()
+                    .ok()
+                    .filter(|_| self.heatmap)
+                    .and_then(|m| m.modified().ok())
+                    .map(|modified| self.heat_color(modified));
+                let style = match heat {
+                    Some(color) => Style::default().fg(color),
+                    None if is_symlink => Style::default().fg(Color::Cyan),
+                    None if is_dir => Style::default().fg(Color::Blue),
+                    None => Style::default(),
+                };
+                let marked = self.marked.contains(&entry.path());
+                let style = if marked {
+                    style.bg(Color::DarkGray)
+                } else {
+                    style
+                };
+                let mut badges = Vec::new();
+                if is_symlink {
+                    badges.push(Span::raw("@"));
+                }
+                if marked {
+                    badges.push(Span::styled(" *", Style::default().fg(Color::Magenta)));
+                }
+                if let Ok(metadata) = &metadata
+                    && !metadata.is_dir()
+                {
+                    let links = hardlinks::link_count(metadata);
+                    if self.config.show_hard_links && links > 1 {
+                        badges.push(Span::styled(
+                            format!(" [{} links]", links),
+                            Style::default().fg(Color::DarkGray),
+                        ));
+                    }
+                    if metadata.permissions().readonly() {
+                        badges.push(Span::styled(" [ro]", Style::default().fg(Color::Yellow)));
+                    }
+                }
+                // Inside a repository every name makes room for a marker, so
+                // the names stay lined up.
+                let marker = self.git_statuses.as_ref().map(|statuses| {
+                    match statuses.get(&entry.file_name()) {
+                        Some(git::Status::Modified) => {
+                            Span::styled("M ", Style::default().fg(Color::Yellow))
+                        }
+                        Some(git::Status::Staged) => {
+                            Span::styled("+ ", Style::default().fg(Color::Green))
+                        }
+                        Some(git::Status::Untracked) => {
+                            Span::styled("? ", Style::default().fg(Color::Red))
+                        }
+                        None => Span::raw("  "),
+                    }
+                });
+                // Unstyled, so it takes the entry's color.
+                let icon = self
+                    .use_icons
+                    .then(|| Span::raw(format!("{} ", icons::icon(&entry.file_name(), is_dir))));
+                let badge_width: usize = badges.iter().map(|b| b.width()).sum::<usize>()
+                    + marker.as_ref().map_or(0, |m| m.width())
+                    + icon.as_ref().map_or(0, |i| i.width());
+                let mut name_line =
+                    self.entry_name_line(entry, is_dir, width.saturating_sub(badge_width));
+                if let Some(icon) = icon {
+                    name_line.spans.insert(0, icon);
+                }
+                if let Some(marker) = marker {
+                    name_line.spans.insert(0, marker);
+                }
+                name_line.spans.extend(badges);
+                let mut text = Text::from(name_line);
+                if self.two_line_entries {
+                    text.push_line(Self::entry_metadata_line(entry));
+                }
+                ListItem::new(text).style(style)
+            })
+            .collect()
+    }
+}
+
+/// Splits `text` into spans in `style`, picking out the characters the
+/// filter matched. `matched` holds char indices into the whole name, of
+/// which `text` starts at char `offset`.
+fn match_spans(text: &str, offset: usize, matched: &[usize], style: Style) -> Vec<Span<'static>> {
+    let highlight = style.fg(Color::Yellow).add_modifier(Modifier::BOLD);
+    let mut spans = Vec::new();
+    let mut run = String::new();
+    let mut run_matched = false;
+    for (i, c) in text.chars().enumerate() {
+        let is_match = matched.contains(&(offset + i));
+        if is_match != run_matched && !run.is_empty() {
+            let run_style = if run_matched { highlight } else { style };
+            spans.push(Span::styled(std::mem::take(&mut run), run_style));
+        }
+        run_matched = is_match;
+        run.push(c);
+    }
+    if !run.is_empty() {
+        spans.push(Span::styled(
+            run,
+            if run_matched { highlight } else { style },
+        ));
+    }
+    spans
+}
+
+/// Highlights the first `lines` lines of `path` with `bat`, or returns
+/// `None` to fall back to syntect if bat fails.
+fn bat_preview(bat: &Path, path: &Path, lines: usize) -> Option<Vec<Line<'static>>> {
+    let output = Command::new(bat)
+        .args([
+            "--color=always",
+            "--style=plain",
+            "--paging=never",
+            "--wrap=never",
+        ])
+        .arg(format!("--line-range=:{}", lines.max(1)))
+        .arg("--")
+        .arg(path)
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    Some(ansi::parse(&String::from_utf8_lossy(&output.stdout)))
+}
+
+/// The number the gutter shows for the line at `index` in the preview,
+/// whose first line is line `first_line` of the file, with the line at
+/// `current` the one relative numbers count from.
+fn line_number(mode: LineNumbers, first_line: usize, index: usize, current: usize) -> usize {
+    match mode {
+        LineNumbers::Hybrid if index == current => first_line + index,
+        LineNumbers::Relative | LineNumbers::Hybrid => index.abs_diff(current),
+        LineNumbers::Absolute => first_line + index,
+    }
+}
+
+/// Looks for bat on `PATH`; Debian and Ubuntu install it as `batcat`.
+fn find_bat() -> Option<PathBuf> {
+    let path = env::var_os("PATH")?;
+    ["bat", "batcat"].iter().find_map(|name| {
+        env::split_paths(&path)
+            .map(|dir| dir.join(name))
+            .find(|candidate| candidate.is_file())
+    })
+}
+
+/// Clears every write bit of `path`, or gives write permission back to its
+/// owner. Group and other write access isn't restored; that's for chmod.
+#[cfg(unix)]
+fn set_read_only(path: &Path, read_only: bool) -> io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let mut permissions = fs::metadata(path)?.permissions();
+    let mode = permissions.mode();
+    permissions.set_mode(if read_only {
+        mode & !0o222
+    } else {
+        mode | 0o200
+    });
+    fs::set_permissions(path, permissions)
+}
+
+#[cfg(not(unix))]
+fn set_read_only(path: &Path, read_only: bool) -> io::Result<()> {
+    let mut permissions = fs::metadata(path)?.permissions();
+    permissions.set_readonly(read_only);
+    fs::set_permissions(path, permissions)
+}
+
+/// Hands the terminal to `command` until it exits, then takes it back and
+/// redraws from scratch.
+fn run_suspended(
+    terminal: &mut Terminal<CrosstermBackend<io::Stdout>>,
+    command: &mut Command,
+) -> io::Result<ExitStatus> {
+    restore_terminal()?;
+    let status = command.status();
+    enable_raw_mode()?;
+    stdout()
+        .execute(EnterAlternateScreen)?
+        .execute(EnableMouseCapture)?;
+    terminal.clear()?;
+    status
+}
+
+/// Runs another lazycat inside `dir`, suspending this one until it exits.
+fn open_nested_instance(app: &mut App, terminal: &mut Terminal<CrosstermBackend<io::Stdout>>) {
+    let Some(dir) = app.entries.get(app.selected).map(|e| e.path()) else {
+        return;
+    };
+    if !dir.is_dir() {
+        app.status_message = Some("Select a directory to open a nested lazycat in".into());
+        return;
+    }
+    let exe = match env::current_exe() {
+        Ok(exe) => exe,
+        Err(e) => {
+            app.status_message = Some(format!("Cannot locate the lazycat binary: {}", e));
+            return;
+        }
+    };
+    match run_suspended(terminal, Command::new(exe).arg(&dir).current_dir(&dir)) {
+        Ok(status) if status.success() => {}
+        Ok(status) => app.status_message = Some(format!("Nested lazycat exited with {}", status)),
+        Err(e) => app.status_message = Some(format!("Cannot start nested lazycat: {}", e)),
+    }
+    // The child may have changed anything on disk.
+    app.reread_entries();
+}
+
+/// Draws the key bindings and the quickmarks over everything else, in as many
+/// columns as it takes to fit the screen.
+fn draw_help(frame: &mut Frame, keys: &Keymap, quickmarks: &HashMap<char, PathBuf>) {
+    let key = Style::default().fg(Color::Yellow);
+    let mut help = keymap::help(keys);
+    help.extend(fixed_key_help());
+    let key_width = help.iter().map(|(k, _)| k.len()).max().unwrap_or(0);
+    let mut rows: Vec<Line> = help
+        .iter()
+        .map(|(k, action)| {
+            Line::from(vec![
+                Span::styled(format!("{:<key_width$}  ", k), key),
+                Span::raw(*action),
+            ])
+        })
+        .collect();
+    let mut marks: Vec<_> = quickmarks.iter().collect();
+    marks.sort();
+    if !marks.is_empty() {
+        rows.push(Line::from(""));
+        rows.push(Line::from(Span::styled(
+            "Quickmarks",
+            Style::default().add_modifier(Modifier::BOLD),
+        )));
+        rows.extend(marks.into_iter().map(|(mark, dir)| {
+            Line::from(vec![
+                Span::styled(format!("{:<key_width$}  ", mark), key),
+                Span::raw(dir.display().to_string()),
+            ])
+        }));
+    }
+
+    let height = frame.area().height.saturating_sub(2).max(1) as usize;
+    let columns: Vec<&[Line]> = rows.chunks(height).collect();
+    let column_width = rows.iter().map(|l| l.width()).max().unwrap_or(0) + 3;
+    let lines: Vec<Line> = (0..height.min(rows.len()))
+        .map(|i| {
+            let mut line = Line::default();
+            for column in &columns {
+                if let Some(row) = column.get(i) {
+                    let padding = column_width - row.width();
+                    line.spans.extend(row.spans.iter().cloned());
+                    line.spans.push(Span::raw(" ".repeat(padding)));
+                }
+            }
+            line
+        })
+        .collect();
+    let width = (columns.len() * column_width) as u16 + 2;
+    let area = centered_rect(frame.area(), width, lines.len() as u16 + 2);
+    frame.render_widget(Clear, area);
+    frame.render_widget(
+        Paragraph::new(lines).block(
+            Block::default()
+                .title("Keys (any key closes)")
+                .borders(Borders::ALL),
+        ),
+        area,
+    );
+}
+
+/// Runs one of the single-key commands. `Action::Quit` is left to the
+/// caller, which owns the main loop.
+fn run_action(
+    app: &mut App,
+    terminal: &mut Terminal<CrosstermBackend<io::Stdout>>,
+    action: Action,
+) -> io::Result<()> {
+    match action {
+        Action::Quit => {}
+        Action::Down => app.move_down(),
+        Action::Up => app.move_up(),
+        Action::Open => app.step_right(),
+        Action::Parent => app.step_left(),
+        Action::Bottom => app.move_to_bottom(),
+        Action::NextLetter => app.next_letter_group(),
+        Action::PreviousLetter => app.prev_letter_group(),
+        Action::Recent => app.cycle_recent_dirs(),
+        Action::Filter => app.start_filter(),
+        Action::Hidden => app.toggle_hidden(),
+        Action::GitIgnored => app.toggle_git_ignored(),
+        Action::Sort => app.cycle_sort_mode(),
+        Action::ReverseSort => app.toggle_sort_direction(),
+        Action::LoadAll => app.load_all_entries(),
+        Action::PreviewDown if app.preview_search.is_some() => app.step_preview_search(false),
+        Action::PreviewDown => app.scroll_preview_down(15),
+        Action::PreviewUp => app.scroll_preview_up(15),
+        Action::Wrap => app.toggle_wrap(),
+        Action::ScrollLeft => app.scroll_preview_left(app.preview_area.width / 2),
+        Action::ScrollRight => app.scroll_preview_right(app.preview_area.width / 2),
+        Action::NextPage => app.page_preview_down(),
+        Action::PreviousPage => app.page_preview_up(),
+        Action::SearchPreview => app.start_preview_search(),
+        Action::PreviousMatch => app.step_preview_search(true),
+        Action::ForcePreview => app.force_preview(),
+        Action::Theme => app.cycle_theme(),
+        Action::CopyPath => app.copy_selected_path(false),
+        Action::CopyName => app.copy_selected_path(true),
+        Action::CopyContents => app.copy_file_contents(),
+        Action::Emit => app.emit_selection(),
+        Action::Checksum => app.start_checksum(),
+        Action::AllocatedSize => app.show_allocated_size(),
+        Action::ReadOnly => app.toggle_read_only(),
+        Action::HardLinks => app.pick_hard_links(),
+        Action::Mark => app.toggle_mark(),
+        Action::Delete => app.confirm_delete(),
+        Action::Rename => app.start_rename(),
+        Action::Cut => app.fill_register(true),
+        Action::Copy => app.fill_register(false),
+        Action::Paste => app.paste(),
+        Action::NewFile => app.start_create(false),
+        Action::NewDirectory => app.start_create(true),
+        Action::MoveToQuickmark => app.pick_file_away_dir(false),
+        Action::CopyToQuickmark => app.pick_file_away_dir(true),
+        Action::NewFromTemplate => app.pick_template(),
+        Action::Shell => app.open_split_shell(),
+        Action::Nested => open_nested_instance(app, terminal),
+        Action::Help => app.show_help = true,
+    }
+    Ok(())
+}
+
+/// Leaves raw mode, mouse capture and the alternate screen.
+fn restore_terminal() -> io::Result<()> {
+    disable_raw_mode()?;
+    stdout()
+        .execute(DisableMouseCapture)?
+        .execute(LeaveAlternateScreen)?;
+    Ok(())
+}
+
+fn main() -> io::Result<()> {
+    let start = env::args_os().nth(1).map(PathBuf::from);
+    if let Some(path) = &start
+        && let Err(e) = fs::metadata(path)
+    {
+        eprintln!("lazycat: {}: {}", path.display(), e);
+        std::process::exit(1);
+    }
+
+    enable_raw_mode()?;
+    stdout()
+        .execute(EnterAlternateScreen)?
+        .execute(EnableMouseCapture)?;
+    // Without this a panic leaves the terminal in raw mode, its message
+    // lost with the alternate screen.
+    let default_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        let _ = restore_terminal();
+        default_hook(info);
+    }));
+
+    let result = run(start);
+    restore_terminal()?;
+    result
+}
+
+fn run(start: Option<PathBuf>) -> io::Result<()> {
+    let backend = CrosstermBackend::new(stdout());
+    let mut terminal = Terminal::new(backend)?;
+
+    let mut app = App::new(start)?;
+    let mut list_state = ListState::default();
+    // The first key of a two-key command, and when it was pressed.
+    let mut pending_key: Option<(char, Instant)> = None;
+
+    loop {
+        app.receive_highlights();
+        app.receive_image();
+        app.receive_entries();
+        app.receive_git();
+        app.receive_checksum();
+        app.receive_emits();
+        app.settle_recent_cycle(false);
+        app.settle_preview(false);
+        terminal.draw(|frame| {
+            let rows = Layout::default()
+                .direction(Direction::Vertical)
+                .constraints([Constraint::Min(1), Constraint::Length(1)])
+                .split(frame.area());
+            let (list_width, preview_width) =
+                (Constraint::Percentage(40), Constraint::Percentage(60));
+            let constraints = if app.swap_panes {
+                [preview_width, list_width]
+            } else {
+                [list_width, preview_width]
+            };
+            let chunks = Layout::default()
+                .direction(Direction::Horizontal)
+                .constraints(constraints)
+                .split(rows[0]);
+            let (list_area, preview_area) = if app.swap_panes {
+                (chunks[1], chunks[0])
+            } else {
+                (chunks[0], chunks[1])
+            };
+
+            // Only the rows inside the pane are turned into `ListItem`s; the
+            // window starts at our own offset, so ratatui's is pinned to 0.
+            let list_height = list_area.height.saturating_sub(2) as usize;
+            app.list_rows = list_height / app.entry_height();
+            let range = app.visible_range(app.list_rows);
+            let window_start = range.start;
+            let name_width = list_area.width.saturating_sub(4) as usize;
+            let items = app.get_list_items(range, name_width);
+            let mut list_title = app.current_dir.to_string_lossy().to_string();
+            match app.type_filter {
+                Some(EntryKind::Directory) => list_title.push_str(" [dirs only]"),
+                Some(EntryKind::File) => list_title.push_str(" [files only]"),
+                None => {}
+            }
+            if app.git_ignored.is_some() {
+                list_title.push_str(" [not ignored]");
+            }
+            if app.sort_mode != SortMode::Name || app.sort_descending {
+                let arrow = if app.sort_descending { "v" } else { "^" };
+                list_title.push_str(&format!(" [by {} {}]", app.sort_mode.name(), arrow));
+            }
+            if let Some(query) = &app.filter {
+                let cursor = if app.filter_editing { "_" } else { "" };
+                list_title.push_str(&format!(" [/{}{}]", query, cursor));
+            }
+            if app.listing_loading {
+                list_title.push_str(&format!(" (loading... {})", app.entries_read));
+            }
+            if let Some(cap) = app.entries_capped {
+                list_title.push_str(&format!(" ... ({}+ entries, showing first {})", cap, cap));
+            }
+            let list = List::new(items)
+                .block(Block::default().title(list_title).borders(Borders::ALL))
+                .highlight_style(
+                    Style::default()
+                        .bg(Color::Blue)
+                        .fg(Color::White)
+                        .add_modifier(Modifier::BOLD),
+                )
+                .highlight_symbol("> ");
+
+            if app.entries.is_empty() {
+                list_state.select(None);
+            } else {
+                list_state.select(Some(app.selected - window_start));
+            }
+            *list_state.offset_mut() = 0;
+            frame.render_stateful_widget(list, list_area, &mut list_state);
+
+            let mut preview_title = if let Some(entry) = app.entries.get(app.selected) {
+                entry.file_name().to_string_lossy().to_string()
+            } else {
+                "Preview".to_string()
+            };
+            if let Some(target) = &app.preview_target
+                && let Some(name) = target.file_name()
+            {
+                preview_title.push_str(&format!(" -> {}", name.to_string_lossy()));
+            }
+            if app.preview_lines.len() > usize::from(app.preview_height) {
+                preview_title.push_str(&format!(
+                    " [{}/{}]",
+                    app.preview_scroll + 1,
+                    app.preview_lines.len()
+                ));
+            }
+            if let Some(paged) = &app.preview_page {
+                preview_title.push_str(&format!(
+                    " (page {} of {})",
+                    paged.page + 1,
+                    paged.page_count
+                ));
+            }
+            if let Some(search) = &app.preview_search {
+                preview_title.push_str(&format!(
+                    " [{}: {}/{}]",
+                    search.query,
+                    (search.current + 1).min(search.matches.len()),
+                    search.matches.len()
+                ));
+            }
+            if app.highlighting {
+                preview_title.push_str(" (highlighting...)");
+            }
+            if let Some(error) = &app.preview_stale {
+                preview_title.push_str(&format!(" [stale: {}]", error));
+            }
+            app.preview_height = preview_area.height.saturating_sub(2);
+            app.list_area = list_area;
+            app.preview_area = preview_area;
+
+            let mut preview_lines = app.preview_lines.clone();
+            if let Some(search) = &app.preview_search {
+                let found = Style::default().bg(Color::Yellow).fg(Color::Black);
+                let current = Style::default().bg(Color::LightRed).fg(Color::Black);
+                for (i, m) in search.matches.iter().enumerate() {
+                    let style = if i == search.current { current } else { found };
+                    if let Some(line) = preview_lines.get_mut(m.line) {
+                        search::highlight(line, &m.columns, style);
+                    }
+                }
+            }
+            // Scrolled sideways before numbering, so the numbers stay put.
+            if !app.wrap_enabled && app.preview_hscroll > 0 {
+                for line in &mut preview_lines {
+                    skip_columns(line, usize::from(app.preview_hscroll));
+                }
+            }
+            let mut gutter_width = 0;
+            if app.show_line_numbers
+                && let Some(first_line) = app.preview_first_line
+            {
+                let numbered = preview_lines.len() - usize::from(app.preview_truncated);
+                let last_line = first_line + numbered.saturating_sub(1);
+                let digits = last_line.to_string().len();
+                let dim = Style::default().fg(Color::DarkGray);
+                let current = usize::from(match app.config.current_line {
+                    CurrentLine::Top => app.preview_scroll,
+                    CurrentLine::Middle => app.preview_scroll + app.preview_height / 2,
+                })
+                .min(numbered.saturating_sub(1));
+                let mode = app.config.line_numbers;
+                for (index, line) in preview_lines.iter_mut().take(numbered).enumerate() {
+                    let number = line_number(mode, first_line, index, current);
+                    // Counting from a line only helps if it stands out.
+                    let style = if mode != LineNumbers::Absolute && index == current {
+                        Style::default().fg(Color::Yellow)
+                    } else {
+                        dim
+                    };
+                    line.spans
+                        .insert(0, Span::styled(format!("{:>digits$} ", number), style));
+                }
+                gutter_width = digits as u16 + 1;
+            }
+            let mut block = Block::default().title(preview_title).borders(Borders::ALL);
+            if app.preview_focused {
+                block = block.border_style(Style::default().fg(Color::Yellow));
+            }
+            let preview = if app.wrap_enabled {
+                // Wrapped lines take more than a row each, so start from the
+                // top line rather than scrolling by rows.
+                let top = usize::from(app.preview_scroll).min(preview_lines.len());
+                Paragraph::new(preview_lines.split_off(top))
+                    .block(block)
+                    .wrap(Wrap { trim: false })
+            } else {
+                Paragraph::new(preview_lines)
+                    .block(block)
+                    .scroll((app.preview_scroll, 0))
+            };
+
+            frame.render_widget(preview, preview_area);
+
+            let content_length = app.preview_lines.len();
+            let viewport = usize::from(app.preview_height);
+            if content_length > viewport {
+                // Positioned so the thumb reaches the bottom along with the
+                // last line, not when it is the only one left on screen.
+                let mut scrollbar = ScrollbarState::new(content_length.saturating_sub(viewport))
+                    .position(usize::from(app.preview_scroll))
+                    .viewport_content_length(viewport);
+                frame.render_stateful_widget(
+                    Scrollbar::new(ScrollbarOrientation::VerticalRight)
+                        .begin_symbol(None)
+                        .end_symbol(None),
+                    preview_area.inner(Margin::new(0, 1)),
+                    &mut scrollbar,
+                );
+            }
+
+            if app.show_guide {
+                let inner = preview_area.inner(Margin::new(1, 1));
+                let column = app.config.guide_column - 1;
+                let x = inner
+                    .x
+                    .saturating_add(gutter_width)
+                    .saturating_add(column.saturating_sub(app.preview_hscroll));
+                if column >= app.preview_hscroll && x < inner.right() {
+                    for y in inner.top()..inner.bottom() {
+                        frame.buffer_mut()[(x, y)].set_bg(app.config.guide_color);
+                    }
+                }
+            }
+
+            if let Some(picker) = &app.picker {
+                let height = picker.items.len() as u16 + 2;
+                let width = picker
+                    .items
+                    .iter()
+                    .map(|item| item.chars().count())
+                    .max()
+                    .unwrap_or(0)
+                    .max(picker.title.chars().count()) as u16
+                    + 6;
+                let area = centered_rect(frame.area(), width, height);
+                let items: Vec<ListItem> = picker
+                    .items
+                    .iter()
+                    .map(|item| ListItem::new(item.as_str()))
+                    .collect();
+                let list = List::new(items)
+                    .block(
+                        Block::default()
+                            .title(picker.title.as_str())
+                            .borders(Borders::ALL),
+                    )
+                    .highlight_style(
+                        Style::default()
+                            .bg(Color::Blue)
+                            .fg(Color::White)
+                            .add_modifier(Modifier::BOLD),
+                    )
+                    .highlight_symbol("> ");
+                let mut state = ListState::default().with_selected(Some(picker.selected));
+                frame.render_widget(Clear, area);
+                frame.render_stateful_widget(list, area, &mut state);
+            }
+
+            if let Some(cycle) = &app.recent_cycle {
+                let lines: Vec<Line> = app
+                    .state
+                    .recent_dirs
+                    .iter()
+                    .enumerate()
+                    .skip(1)
+                    .map(|(i, dir)| {
+                        let text = dir.display().to_string();
+                        if i == cycle.index {
+                            Line::from(Span::styled(
+                                format!("> {}", text),
+                                Style::default()
+                                    .bg(Color::Blue)
+                                    .fg(Color::White)
+                                    .add_modifier(Modifier::BOLD),
+                            ))
+                        } else {
+                            Line::from(format!("  {}", text))
+                        }
+                    })
+                    .collect();
+                let width = lines.iter().map(|l| l.width()).max().unwrap_or(0) as u16 + 2;
+                let area = centered_rect(frame.area(), width.max(20), lines.len() as u16 + 2);
+                frame.render_widget(Clear, area);
+                frame.render_widget(
+                    Paragraph::new(lines)
+                        .block(Block::default().title("Recent").borders(Borders::ALL)),
+                    area,
+                );
+            }
+
+            if let Some(('`' | '\'', pressed)) = pending_key
+                && pressed.elapsed() < PENDING_KEY_TIMEOUT
+                && !app.state.quickmarks.is_empty()
+            {
+                let mut marks: Vec<_> = app.state.quickmarks.iter().collect();
+                marks.sort();
+                let lines: Vec<Line> = marks
+                    .into_iter()
+                    .map(|(mark, dir)| {
+                        Line::from(vec![
+                            Span::styled(format!("{}  ", mark), Style::default().fg(Color::Yellow)),
+                            Span::raw(dir.display().to_string()),
+                        ])
+                    })
+                    .collect();
+                let width = lines.iter().map(|l| l.width()).max().unwrap_or(0) as u16 + 2;
+                let area = centered_rect(frame.area(), width.max(20), lines.len() as u16 + 2);
+                frame.render_widget(Clear, area);
+                frame.render_widget(
+                    Paragraph::new(lines)
+                        .block(Block::default().title("Quickmarks").borders(Borders::ALL)),
+                    area,
+                );
+            }
+
+            if app.show_help {
+                draw_help(frame, &app.config.keys, &app.state.quickmarks);
+            }
+
+            if let Some(input) = &app.input {
+                let mut spans = vec![
+                    Span::styled(input.prompt.as_str(), Style::default().fg(Color::Yellow)),
+                    Span::raw(input.text.as_str()),
+                    Span::styled("_", Style::default().add_modifier(Modifier::SLOW_BLINK)),
+                ];
+                if let Some(error) = &input.error {
+                    spans.push(Span::styled(
+                        format!("  {}", error),
+                        Style::default().fg(Color::Red),
+                    ));
+                }
+                frame.render_widget(Paragraph::new(Line::from(spans)), rows[1]);
+            } else if let Some(prompt) = app.paste.as_ref().and_then(|p| p.prompt.as_deref()) {
+                let prompt = Span::styled(prompt, Style::default().fg(Color::Yellow));
+                frame.render_widget(Paragraph::new(prompt), rows[1]);
+            } else if let Some(confirm) = &app.confirm {
+                let prompt =
+                    Span::styled(confirm.prompt.as_str(), Style::default().fg(Color::Yellow));
+                frame.render_widget(Paragraph::new(prompt), rows[1]);
+            } else if let Some(message) = &app.status_message {
+                frame.render_widget(Paragraph::new(message.as_str()), rows[1]);
+            } else {
+                let status = Span::styled(app.status_line(), Style::default().fg(Color::DarkGray));
+                frame.render_widget(Paragraph::new(status), rows[1]);
+            }
+        })?;
+
+        // An image is drawn for the pane's size, which the first frame or a
+        // resize only settles now.
+        if app
+            .preview_image_size
+            .is_some_and(|size| size != app.preview_cells())
+        {
+            app.resize_image();
+        }
+
+        // Poll briskly while highlighted chunks or directory entries are
+        // still streaming in or a recent-directory cycle is waiting to
+        // settle, and wake up in time to catch up the preview.
+        let mut timeout = if app.highlighting
+            || app.drawing_image
+            || app.listing_loading
+            || app.recent_cycle.is_some()
+        {
+            Duration::from_millis(16)
+        } else {
+            Duration::from_millis(100)
+        };
+        if let Some(moved) = app.preview_dirty {
+            timeout = timeout.min(PREVIEW_DEBOUNCE.saturating_sub(moved.elapsed()));
+        }
+        let event = if event::poll(timeout)? {
+            Some(event::read()?)
+        } else {
+            None
+        };
+        // Popups and prompts are keyboard only.
+        if let Some(Event::Mouse(mouse)) = event
+            && !app.show_help
+            && app.confirm.is_none()
+            && app.paste.is_none()
+            && app.input.is_none()
+            && app.picker.is_none()
+            && app.recent_cycle.is_none()
+        {
+            app.handle_mouse(mouse);
+        }
+        if let Some(Event::Key(key)) = event
+            && key.kind == KeyEventKind::Press
+        {
+            app.status_message = None;
+            if app.show_help {
+                app.show_help = false;
+                continue;
+            }
+            // The keymap is for plain keys; Ctrl and Alt chords are their own.
+            let command_modifiers = KeyModifiers::CONTROL | KeyModifiers::ALT;
+            // Only plain up and down movement may leave the preview behind;
+            // everything else may act on what it shows.
+            let moves = match key.code {
+                KeyCode::Up | KeyCode::Down => true,
+                KeyCode::Char(c) if !key.modifiers.intersects(command_modifiers) => {
+                    matches!(app.config.keys.get(&c), Some(Action::Up | Action::Down))
+                }
+                _ => false,
+            };
+            if !moves {
+                app.settle_preview(true);
+            }
+            if app.paste.is_some() {
+                match key.code {
+                    KeyCode::Char('o') => app.resume_paste(Some(Collision::Overwrite)),
+                    KeyCode::Char('s') => app.resume_paste(Some(Collision::Skip)),
+                    KeyCode::Char('r') => app.resume_paste(Some(Collision::Rename)),
+                    KeyCode::Esc => {
+                        if let Some(paste) = app.paste.take() {
+                            app.finish_paste(paste);
+                        }
+                    }
+                    _ => {}
+                }
+                continue;
+            }
+            if let Some(confirm) = app.confirm.take() {
+                match key.code {
+                    KeyCode::Char('y') | KeyCode::Char('Y') => app.run_confirmed(confirm.action),
+                    _ => app.status_message = Some("Cancelled".into()),
+                }
+                continue;
+            }
+            if app.input.is_some() {
+                app.handle_input_key(key.code);
+                continue;
+            }
+            if app.recent_cycle.is_some() {
+                match key.code {
+                    KeyCode::Char('-') => {
+                        app.cycle_recent_dirs();
+                        continue;
+                    }
+                    KeyCode::Esc => {
+                        app.recent_cycle = None;
+                        continue;
+                    }
+                    KeyCode::Enter => {
+                        app.settle_recent_cycle(true);
+                        continue;
+                    }
+                    // Any other key lands the jump, then does its own thing.
+                    _ => app.settle_recent_cycle(true),
+                }
+            }
+            if app.picker.is_some() {
+                app.handle_picker_key(key.code);
+                continue;
+            }
+            if app.filter_editing {
+                app.handle_filter_key(key.code);
+                continue;
+            }
+            if let Some((prefix, pressed)) = pending_key.take()
+                && pressed.elapsed() < PENDING_KEY_TIMEOUT
+            {
+                if let KeyCode::Char(c) = key.code
+                    && let Some(command) = fixed_command(Some(prefix), &key)
+                {
+                    command(&mut app, c);
+                }
+                continue;
+            }
+            if let Some(command) = fixed_command(None, &key) {
+                command(&mut app, ' ');
+                if app.quitting {
+                    break;
+                }
+                continue;
+            }
+            match key.code {
+                KeyCode::Char(c) if !key.modifiers.intersects(command_modifiers) => {
+                    match app.config.keys.get(&c).copied() {
+                        Some(Action::Quit) => break,
+                        Some(action) => run_action(&mut app, &mut terminal, action)?,
+                        None if keymap::PREFIX_KEYS.contains(&c) => {
+                            pending_key = Some((c, Instant::now()));
+                        }
+                        None => {}
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    // Best effort: failing to remember recent directories isn't worth an
+    // error on the way out.
+    let _ = app.state.save();
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn complete_utf8_len_drops_a_character_split_at_the_end() {
+        // 2-, 3- and 4-byte characters with their last byte cut off.
+        assert_eq!(complete_utf8_len(&"abé".as_bytes()[..3]), 2);
+        assert_eq!(complete_utf8_len(&"ab€".as_bytes()[..4]), 2);
+        assert_eq!(complete_utf8_len(&"ab€".as_bytes()[..3]), 2);
+        assert_eq!(complete_utf8_len(&"ab😀".as_bytes()[..5]), 2);
+        assert_eq!(complete_utf8_len(&"ab😀".as_bytes()[..3]), 2);
+    }
+
+    #[test]
+    fn complete_utf8_len_keeps_a_complete_character() {
+        for text in ["abc", "abé", "ab€", "ab😀", "é", ""] {
+            assert_eq!(complete_utf8_len(text.as_bytes()), text.len());
+        }
+    }
+
+    #[test]
+    fn complete_utf8_len_leaves_invalid_bytes_alone() {
+        // Stray continuation bytes and bytes that never start a character
+        // are for the lossy decoding to deal with.
+        assert_eq!(complete_utf8_len(b"ab\x80\x80\x80"), 5);
+        assert_eq!(complete_utf8_len(b"ab\xff"), 3);
+        assert_eq!(complete_utf8_len(b"\x80"), 1);
+    }
+
+    #[test]
+    fn read_page_caps_an_overlong_line_at_a_character_boundary() {
+        let cap = 2 * PREVIEW_PAGE_BYTES as usize;
+        let mut content = "a".repeat(cap - 1);
+        content.push('é');
+        content.push_str(&"a".repeat(100));
+        let path = env::temp_dir().join(format!("lazycat-read-page-{}", std::process::id()));
+        fs::write(&path, &content).unwrap();
+        let page = read_page(&path, 0);
+        fs::remove_file(&path).unwrap();
+        let page = page.unwrap();
+        assert_eq!(page.len(), cap - 1);
+        assert!(std::str::from_utf8(&page).is_ok());
+    }
+
+    #[test]
+    fn line_numbers_count_from_the_current_line() {
+        // A page starting at line 41, with the third line shown current.
+        let numbers =
+            |mode| -> Vec<usize> { (0..5).map(|i| line_number(mode, 41, i, 2)).collect() };
+        assert_eq!(numbers(LineNumbers::Absolute), [41, 42, 43, 44, 45]);
+        assert_eq!(numbers(LineNumbers::Relative), [2, 1, 0, 1, 2]);
+        assert_eq!(numbers(LineNumbers::Hybrid), [2, 1, 43, 1, 2]);
+    }
+
+    #[test]
+    fn match_spans_splits_matched_runs() {
+        let plain = Style::default();
+        let highlight = plain.fg(Color::Yellow).add_modifier(Modifier::BOLD);
+        let spans = |text, offset, matched: &[usize]| -> Vec<(String, Style)> {
+            match_spans(text, offset, matched, plain)
+                .into_iter()
+                .map(|span| (span.content.into_owned(), span.style))
+                .collect()
+        };
+        let owned = |parts: &[(&str, Style)]| -> Vec<(String, Style)> {
+            parts
+                .iter()
+                .map(|(text, style)| (text.to_string(), *style))
+                .collect()
+        };
+        assert_eq!(
+            spans("main.rs", 0, &[0, 5, 6]),
+            owned(&[("m", highlight), ("ain.", plain), ("rs", highlight)])
+        );
+        // The indices are into the whole name, of which this is the tail.
+        assert_eq!(
+            spans("rs", 5, &[0, 5]),
+            owned(&[("r", highlight), ("s", plain)])
+        );
+        assert_eq!(
+            spans("fête", 0, &[1, 2]),
+            owned(&[("f", plain), ("êt", highlight), ("e", plain)])
+        );
+        assert_eq!(spans("", 0, &[]), owned(&[]));
+    }
+
+    #[test]
+    fn fixed_keys_are_bound_once_behind_the_prefix_keys() {
+        let triggers: Vec<Trigger> = FIXED_KEYS
+            .iter()
+            .flat_map(|(triggers, ..)| triggers.iter().copied())
+            .collect();
+        for (i, trigger) in triggers.iter().enumerate() {
+            assert!(
+                !triggers[..i].contains(trigger),
+                "{:?} bound twice",
+                trigger
+            );
+        }
+        let mut prefixes: Vec<char> = triggers
+            .iter()
+            .filter_map(|trigger| match trigger {
+                Trigger::Prefixed(p, _) | Trigger::PrefixedAny(p) => Some(*p),
+                _ => None,
+            })
+            .collect();
+        prefixes.sort();
+        prefixes.dedup();
+        let mut expected = keymap::PREFIX_KEYS.to_vec();
+        expected.sort();
+        assert_eq!(prefixes, expected);
+    }
+
+    #[test]
+    fn skip_columns_counts_display_width() {
+        let skipped = |spans: Vec<&'static str>, columns| {
+            let mut line = Line::from(spans.into_iter().map(Span::raw).collect::<Vec<_>>());
+            skip_columns(&mut line, columns);
+            line.spans
+                .iter()
+                .map(|span| span.content.to_string())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(skipped(vec!["ab", "cd"], 3), ["d"]);
+        // Each of these takes two columns.
+        assert_eq!(skipped(vec!["日本語"], 2), ["本語"]);
+        assert_eq!(skipped(vec!["日本語"], 3), [" 語"]);
+        assert_eq!(skipped(vec!["e\u{301}x"], 1), ["x"]);
+        assert_eq!(skipped(vec!["ab"], 5), Vec::<String>::new());
+    }
 }
@@ -0,0 +1,317 @@
+//! Light formatting for reStructuredText, AsciiDoc and Org documents in the
+//! preview: headings stand out, bullets become `•`, and code blocks are set
+//! apart. These are line-based heuristics rather than real parsers, so a
+//! document they can't make sense of is left to the syntax highlighter.
+
+use std::path::Path;
+
+use ratatui::{
+    style::{Color, Modifier, Style},
+    text::{Line, Span},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    Rst,
+    AsciiDoc,
+    Org,
+}
+
+impl Format {
+    pub fn from_path(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()?.to_ascii_lowercase().as_str() {
+            "rst" => Some(Self::Rst),
+            "adoc" | "asciidoc" => Some(Self::AsciiDoc),
+            "org" => Some(Self::Org),
+            _ => None,
+        }
+    }
+}
+
+/// Formats `content`, or returns `None` when it doesn't look enough like
+/// `format` to trust the result.
+pub fn render(format: Format, content: &str) -> Option<Vec<Line<'static>>> {
+    let lines: Vec<&str> = content.lines().collect();
+    match format {
+        Format::Rst => rst(&lines),
+        Format::AsciiDoc => asciidoc(&lines),
+        Format::Org => org(&lines),
+    }
+}
+
+#[derive(Default)]
+struct Renderer {
+    lines: Vec<Line<'static>>,
+    /// Whether anything beyond plain paragraphs was recognised.
+    structured: bool,
+}
+
+impl Renderer {
+    fn heading(&mut self, level: usize, text: &str) {
+        let style = match level {
+            1 => Style::default()
+                .fg(Color::Yellow)
+                .add_modifier(Modifier::BOLD | Modifier::UNDERLINED),
+            2 => Style::default()
+                .fg(Color::Cyan)
+                .add_modifier(Modifier::BOLD),
+            _ => Style::default().add_modifier(Modifier::BOLD),
+        };
+        self.lines
+            .push(Line::from(Span::styled(text.trim().to_string(), style)));
+        self.structured = true;
+    }
+
+    fn bullet(&mut self, indent: &str, text: &str) {
+        self.lines.push(Line::from(vec![
+            Span::raw(indent.to_string()),
+            Span::styled("• ", Style::default().fg(Color::Cyan)),
+            Span::raw(text.to_string()),
+        ]));
+        self.structured = true;
+    }
+
+    fn code(&mut self, text: &str) {
+        self.lines.push(Line::from(Span::styled(
+            text.to_string(),
+            Style::default().fg(Color::Green),
+        )));
+        self.structured = true;
+    }
+
+    /// Directives, attributes, comments and block delimiters: shown, but
+    /// out of the way.
+    fn markup(&mut self, text: &str) {
+        self.lines.push(Line::from(Span::styled(
+            text.to_string(),
+            Style::default().fg(Color::DarkGray),
+        )));
+    }
+
+    fn text(&mut self, text: &str) {
+        self.lines.push(Line::raw(text.to_string()));
+    }
+
+    fn finish(self) -> Option<Vec<Line<'static>>> {
+        self.structured.then_some(self.lines)
+    }
+}
+
+/// Splits a bullet item into its indentation and its text, if `line` starts
+/// with one of `markers` followed by a space.
+fn bullet<'a>(line: &'a str, markers: &[char]) -> Option<(&'a str, &'a str)> {
+    let rest = line.trim_start();
+    let indent = &line[..line.len() - rest.len()];
+    let mut chars = rest.chars();
+    let marker = chars.next()?;
+    (markers.contains(&marker) && chars.next() == Some(' ')).then(|| (indent, &rest[2..]))
+}
+
+fn indent_width(line: &str) -> usize {
+    line.len() - line.trim_start().len()
+}
+
+/// Punctuation reStructuredText accepts for section adornments.
+const RST_ADORNMENTS: &str = "=-~^\"'`#*+:._";
+
+/// The character of an adornment line such as `=====`.
+fn rst_adornment(line: &str) -> Option<char> {
+    let line = line.trim_end();
+    let mut chars = line.chars();
+    let c = chars.next()?;
+    (line.len() >= 2 && RST_ADORNMENTS.contains(c) && chars.all(|x| x == c)).then_some(c)
+}
+
+fn rst(lines: &[&str]) -> Option<Vec<Line<'static>>> {
+    let mut out = Renderer::default();
+    // Heading levels follow the order adornment styles first appear in.
+    let mut styles: Vec<(char, bool)> = Vec::new();
+    let mut level_of = |style| match styles.iter().position(|s| *s == style) {
+        Some(i) => i + 1,
+        None => {
+            styles.push(style);
+            styles.len()
+        }
+    };
+    // The indentation a literal block has to exceed, while inside one.
+    let mut literal: Option<usize> = None;
+    let mut i = 0;
+    while i < lines.len() {
+        let line = lines[i];
+        let next = lines.get(i + 1).copied().unwrap_or("");
+        if let Some(base) = literal {
+            if line.trim().is_empty() {
+                out.text("");
+                i += 1;
+                continue;
+            }
+            if indent_width(line) > base {
+                out.code(line);
+                i += 1;
+                continue;
+            }
+            literal = None;
+        }
+
+        let title_fits = |title: &str, adornment: &str| {
+            !title.trim().is_empty() && adornment.trim_end().len() >= title.trim().chars().count()
+        };
+        if let Some(c) = rst_adornment(line)
+            && let Some(&under) = lines.get(i + 2)
+            && rst_adornment(under) == Some(c)
+            && title_fits(next, line)
+        {
+            out.heading(level_of((c, true)), next);
+            i += 3;
+            continue;
+        }
+        if !line.starts_with(char::is_whitespace)
+            && rst_adornment(line).is_none()
+            && let Some(c) = rst_adornment(next)
+            && title_fits(line, next)
+        {
+            out.heading(level_of((c, false)), line);
+            i += 2;
+            continue;
+        }
+
+        let trimmed = line.trim_start();
+        if let Some(directive) = trimmed.strip_prefix(".. ") {
+            if ["code-block::", "code::", "sourcecode::"]
+                .iter()
+                .any(|d| directive.starts_with(d))
+            {
+                literal = Some(indent_width(line));
+            }
+            out.markup(line);
+        } else if let Some((indent, text)) = bullet(line, &['-', '*', '+']) {
+            out.bullet(indent, text);
+        } else if let Some(intro) = line.strip_suffix("::") {
+            // A paragraph ending in `::` introduces a literal block and is
+            // displayed with a single colon, or none if that's all there is.
+            literal = Some(indent_width(line));
+            if !intro.trim().is_empty() {
+                out.text(&format!("{}:", intro));
+            }
+        } else {
+            out.text(line);
+        }
+        i += 1;
+    }
+    out.finish()
+}
+
+/// Splits an AsciiDoc delimiter line like `----` into its character, for
+/// listing (`-`), literal (`.`) and passthrough (`+`) blocks.
+fn asciidoc_delimiter(line: &str) -> Option<char> {
+    let mut chars = line.chars();
+    let c = chars.next()?;
+    (line.len() >= 4 && matches!(c, '-' | '.' | '+') && chars.all(|x| x == c)).then_some(c)
+}
+
+fn asciidoc(lines: &[&str]) -> Option<Vec<Line<'static>>> {
+    let mut out = Renderer::default();
+    let mut lines = lines.iter();
+    while let Some(&line) = lines.next() {
+        if let Some(c) = asciidoc_delimiter(line) {
+            out.markup(line);
+            // An unclosed block means this isn't the document we think it is.
+            loop {
+                let &inner = lines.next()?;
+                if inner == line {
+                    break;
+                }
+                if c == '+' {
+                    out.text(inner);
+                } else {
+                    out.code(inner);
+                }
+            }
+            out.markup(line);
+            continue;
+        }
+        let level = line.chars().take_while(|c| *c == '=').count();
+        if (1..=6).contains(&level) && line[level..].starts_with(' ') {
+            out.heading(level, &line[level..]);
+            continue;
+        }
+        let stars = line.chars().take_while(|c| *c == '*').count();
+        if stars > 0 && line[stars..].starts_with(' ') {
+            out.bullet(&"  ".repeat(stars - 1), line[stars..].trim_start());
+            continue;
+        }
+        if let Some((indent, text)) = bullet(line, &['-']) {
+            out.bullet(indent, text);
+            continue;
+        }
+        let is_attribute = line.starts_with(':')
+            && line[1..]
+                .split_once(':')
+                .is_some_and(|(name, _)| !name.is_empty() && !name.contains(char::is_whitespace));
+        if is_attribute || line.starts_with("//") || (line.starts_with('[') && line.ends_with(']'))
+        {
+            out.markup(line);
+        } else if let Some(title) = line.strip_prefix('.')
+            && title.starts_with(|c: char| c.is_alphanumeric())
+        {
+            // A block title, like `.Example`.
+            out.heading(3, title);
+        } else {
+            out.text(line);
+        }
+    }
+    out.finish()
+}
+
+fn org(lines: &[&str]) -> Option<Vec<Line<'static>>> {
+    let mut out = Renderer::default();
+    let mut lines = lines.iter();
+    while let Some(&line) = lines.next() {
+        let trimmed = line.trim_start();
+        let keyword = trimmed.get(..2).is_some_and(|k| k == "#+");
+        if keyword && trimmed[2..].to_ascii_lowercase().starts_with("begin_") {
+            out.markup(line);
+            // As with AsciiDoc, an unterminated block gives up on formatting.
+            loop {
+                let &inner = lines.next()?;
+                if inner
+                    .trim_start()
+                    .to_ascii_lowercase()
+                    .starts_with("#+end_")
+                {
+                    out.markup(inner);
+                    break;
+                }
+                out.code(inner);
+            }
+            continue;
+        }
+        if keyword {
+            match trimmed.split_once(':') {
+                Some((key, title)) if key.eq_ignore_ascii_case("#+title") => {
+                    out.heading(1, title);
+                }
+                _ => out.markup(line),
+            }
+            continue;
+        }
+        let level = line.chars().take_while(|c| *c == '*').count();
+        if level > 0 && line[level..].starts_with(' ') {
+            // Titles take level 1, so top-level headings start at 2.
+            out.heading(level + 1, &line[level..]);
+            continue;
+        }
+        if trimmed == ":" || trimmed.starts_with(": ") {
+            out.code(line);
+        } else if let Some((indent, text)) = bullet(line, &['-', '+'])
+            .or_else(|| bullet(line, &['*']).filter(|(indent, _)| !indent.is_empty()))
+        {
+            out.bullet(indent, text);
+        } else if trimmed.starts_with("# ") {
+            out.markup(line);
+        } else {
+            out.text(line);
+        }
+    }
+    out.finish()
+}
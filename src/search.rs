@@ -0,0 +1,86 @@
+//! Finding text in the preview and picking it out on top of whatever
+//! highlighting the lines already carry.
+
+use std::ops::Range;
+
+use ratatui::{
+    style::Style,
+    text::{Line, Span},
+};
+
+/// One occurrence of the query: a line and the char columns it covers.
+pub struct Match {
+    pub line: usize,
+    pub columns: Range<usize>,
+}
+
+/// Every non-overlapping occurrence of `query` in `lines`, in order.
+/// Matching ignores case unless `query` has an uppercase letter in it, as
+/// with the `/` filter.
+pub fn find(lines: &[Line], query: &str) -> Vec<Match> {
+    let case_sensitive = query.chars().any(char::is_uppercase);
+    let fold = |c: char| {
+        if case_sensitive {
+            c
+        } else {
+            c.to_lowercase().next().unwrap_or(c)
+        }
+    };
+    let query: Vec<char> = query.chars().map(fold).collect();
+    if query.is_empty() {
+        return Vec::new();
+    }
+    let mut matches = Vec::new();
+    for (index, line) in lines.iter().enumerate() {
+        let text: Vec<char> = line
+            .spans
+            .iter()
+            .flat_map(|span| span.content.chars())
+            .map(fold)
+            .collect();
+        let mut start = 0;
+        while start + query.len() <= text.len() {
+            if text[start..start + query.len()] == query[..] {
+                matches.push(Match {
+                    line: index,
+                    columns: start..start + query.len(),
+                });
+                start += query.len();
+            } else {
+                start += 1;
+            }
+        }
+    }
+    matches
+}
+
+/// Restyles the char `columns` of `line`, patching `style` over the styles
+/// already there.
+pub fn highlight(line: &mut Line<'static>, columns: &Range<usize>, style: Style) {
+    let mut spans = Vec::with_capacity(line.spans.len() + 2);
+    let mut column = 0;
+    for span in line.spans.drain(..) {
+        // Cut the span wherever `columns` starts or ends inside it.
+        let mut run = String::new();
+        let mut run_style = None;
+        for c in span.content.chars() {
+            let char_style = if columns.contains(&column) {
+                span.style.patch(style)
+            } else {
+                span.style
+            };
+            if let Some(previous) = run_style
+                && previous != char_style
+            {
+                spans.push(Span::styled(std::mem::take(&mut run), previous));
+            }
+            run_style = Some(char_style);
+            run.push(c);
+            column += 1;
+        }
+        if let Some(style) = run_style {
+            spans.push(Span::styled(run, style));
+        }
+    }
+    line.spans = spans;
+}
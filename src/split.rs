@@ -0,0 +1,60 @@
+//! Opens a shell in a directory next to lazycat: a new pane of the tmux or
+//! zellij session it runs in, or else a new terminal window.
+
+use std::{
+    env,
+    path::Path,
+    process::{Command, Stdio},
+};
+
+use crate::emit::shell_quote;
+
+/// Starts a shell in `dir`. `configured` is the `[split] command` template,
+/// with `%` standing for the quoted directory, and takes precedence over
+/// detection. Returns a short description of what was opened.
+pub fn open_shell(dir: &Path, configured: Option<&str>) -> Result<String, String> {
+    if let Some(template) = configured {
+        let command = template.replace('%', &shell_quote(&dir.to_string_lossy()));
+        return run(Command::new("sh").arg("-c").arg(&command)).map(|()| format!("`{}`", command));
+    }
+    if env::var_os("TMUX").is_some() {
+        return run(Command::new("tmux").arg("split-window").arg("-c").arg(dir))
+            .map(|()| "a tmux pane".to_string());
+    }
+    if env::var_os("ZELLIJ").is_some() {
+        return run(Command::new("zellij")
+            .args(["action", "new-pane", "--cwd"])
+            .arg(dir))
+        .map(|()| "a zellij pane".to_string());
+    }
+    // A terminal window outlives lazycat, so it is started and left alone.
+    let terminal = env::var("TERMINAL").map_err(|_| {
+        "no tmux or zellij session and $TERMINAL is unset (see [split] command)".to_string()
+    })?;
+    Command::new(&terminal)
+        .current_dir(dir)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .map(|_| format!("{} (no tmux or zellij session)", terminal))
+        .map_err(|e| format!("cannot start {}: {}", terminal, e))
+}
+
+fn run(command: &mut Command) -> Result<(), String> {
+    let output = command
+        .stdin(Stdio::null())
+        .output()
+        .map_err(|e| format!("cannot run {:?}: {}", command.get_program(), e))?;
+    if output.status.success() {
+        Ok(())
+    } else {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        Err(format!(
+            "{:?} failed ({}) {}",
+            command.get_program(),
+            output.status,
+            stderr.lines().next().unwrap_or("").trim()
+        ))
+    }
+}
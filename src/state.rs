@@ -0,0 +1,84 @@
+//! Session state that outlives a single run, kept as plain `key value` lines
+//! in `$XDG_STATE_HOME/lazycat/state` (falling back to `~/.local/state`).
+
+use std::{
+    collections::HashMap,
+    env, fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+/// How many directories the most-recently-used ring remembers.
+const RECENT_DIRS_LEN: usize = 10;
+
+#[derive(Default)]
+pub struct State {
+    pub quickmarks: HashMap<char, PathBuf>,
+    /// Distinct directories visited, most recent first.
+    pub recent_dirs: Vec<PathBuf>,
+}
+
+fn state_file() -> Option<PathBuf> {
+    let dir = env::var_os("XDG_STATE_HOME")
+        .filter(|v| !v.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/state")))?;
+    Some(dir.join("lazycat").join("state"))
+}
+
+impl State {
+    /// Loads the saved state. A missing or unreadable file, or lines that
+    /// don't parse, simply leave the defaults in place.
+    pub fn load() -> Self {
+        let mut state = Self::default();
+        let Some(content) = state_file().and_then(|p| fs::read_to_string(p).ok()) else {
+            return state;
+        };
+        for line in content.lines() {
+            let Some((key, value)) = line.split_once(' ') else {
+                continue;
+            };
+            match key {
+                "quickmark" => {
+                    let mut chars = value.chars();
+                    if let (Some(mark), Some(' ')) = (chars.next(), chars.next()) {
+                        state.quickmarks.insert(mark, PathBuf::from(chars.as_str()));
+                    }
+                }
+                "recent" if state.recent_dirs.len() < RECENT_DIRS_LEN => {
+                    state.recent_dirs.push(PathBuf::from(value));
+                }
+                _ => {}
+            }
+        }
+        state
+    }
+
+    pub fn save(&self) -> io::Result<()> {
+        let path = state_file()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no state directory"))?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let mut marks: Vec<_> = self.quickmarks.iter().collect();
+        marks.sort();
+        let mut file = fs::File::create(path)?;
+        for (mark, dir) in marks {
+            writeln!(file, "quickmark {} {}", mark, dir.display())?;
+        }
+        for dir in &self.recent_dirs {
+            writeln!(file, "recent {}", dir.display())?;
+        }
+        Ok(())
+    }
+
+    /// Moves `dir` to the front of the recent directories.
+    pub fn visit(&mut self, dir: &Path) {
+        if self.recent_dirs.first().is_some_and(|d| d == dir) {
+            return;
+        }
+        self.recent_dirs.retain(|d| d != dir);
+        self.recent_dirs.insert(0, dir.to_path_buf());
+        self.recent_dirs.truncate(RECENT_DIRS_LEN);
+    }
+}
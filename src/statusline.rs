@@ -0,0 +1,99 @@
+//! The user-configurable status line: a template such as
+//! `{index}/{count}  {mode}  {size}  {modified}  {branch}  {marked}` parsed
+//! once at startup and filled in on every frame.
+
+/// A value the status line can show.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Field {
+    Index,
+    Count,
+    Path,
+    Branch,
+    /// Human-readable size of a file, or how many entries a directory has.
+    Size,
+    Mode,
+    Modified,
+    /// How many entries are marked, or nothing when none are.
+    Marked,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Segment {
+    Text(String),
+    Field(Field),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct StatusFormat {
+    segments: Vec<Segment>,
+}
+
+pub const DEFAULT_FORMAT: &str = "{index}/{count}  {mode}  {size}  {modified}  {branch}  {marked}";
+
+impl Field {
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "index" => Some(Self::Index),
+            "count" => Some(Self::Count),
+            "path" => Some(Self::Path),
+            "branch" => Some(Self::Branch),
+            "size" => Some(Self::Size),
+            "mode" => Some(Self::Mode),
+            "modified" => Some(Self::Modified),
+            "marked" => Some(Self::Marked),
+            _ => None,
+        }
+    }
+}
+
+impl StatusFormat {
+    /// Parses a template. `{{` and `}}` stand for literal braces, and
+    /// placeholders that aren't recognised are kept as plain text.
+    pub fn parse(template: &str) -> Self {
+        let mut segments = Vec::new();
+        let mut text = String::new();
+        let mut rest = template;
+        while let Some(c) = rest.chars().next() {
+            if let Some(after) = rest.strip_prefix("{{") {
+                text.push('{');
+                rest = after;
+            } else if let Some(after) = rest.strip_prefix("}}") {
+                text.push('}');
+                rest = after;
+            } else if c == '{'
+                && let Some(end) = rest.find('}')
+                && let Some(field) = Field::from_name(&rest[1..end])
+            {
+                if !text.is_empty() {
+                    segments.push(Segment::Text(std::mem::take(&mut text)));
+                }
+                segments.push(Segment::Field(field));
+                rest = &rest[end + 1..];
+            } else {
+                text.push(c);
+                rest = &rest[c.len_utf8()..];
+            }
+        }
+        if !text.is_empty() {
+            segments.push(Segment::Text(text));
+        }
+        Self { segments }
+    }
+
+    /// Fills in the template, asking `value` for each placeholder.
+    pub fn render(&self, mut value: impl FnMut(Field) -> String) -> String {
+        self.segments
+            .iter()
+            .map(|segment| match segment {
+                Segment::Text(text) => text.clone(),
+                Segment::Field(field) => value(*field),
+            })
+            .collect()
+    }
+}
+
+impl Default for StatusFormat {
+    fn default() -> Self {
+        Self::parse(DEFAULT_FORMAT)
+    }
+}
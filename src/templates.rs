@@ -0,0 +1,40 @@
+//! File templates for "new from template": plain files in a directory
+//! whose `{{name}}` and `{{date}}` placeholders are filled in on creation.
+
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use crate::format;
+
+/// The template files in `dir`, sorted by name.
+pub fn list(dir: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut templates: Vec<PathBuf> = fs::read_dir(dir)?
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .filter(|p| p.is_file())
+        .collect();
+    templates.sort();
+    Ok(templates)
+}
+
+/// Writes a new file at `target` from `template`. `{{name}}` becomes the new
+/// file's name without its extension and `{{date}}` today's date. Fails if
+/// `target` already exists rather than overwriting it.
+pub fn instantiate(template: &Path, target: &Path) -> io::Result<()> {
+    let content = fs::read_to_string(template)?;
+    let name = target
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let content = content
+        .replace("{{name}}", &name)
+        .replace("{{date}}", &format::local_date(SystemTime::now()));
+    let mut file = fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(target)?;
+    io::Write::write_all(&mut file, content.as_bytes())
+}